@@ -0,0 +1,341 @@
+use crate::ecs::{EnemyArchetype, SpawnPattern};
+use serde_derive::Deserialize;
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Node {
+    Sequence {
+        children: Vec<Node>,
+    },
+    Selector {
+        children: Vec<Node>,
+    },
+    Inverter {
+        child: Box<Node>,
+    },
+    Succeeder {
+        child: Box<Node>,
+    },
+    Repeat {
+        child: Box<Node>,
+        #[serde(default)]
+        times: Option<i32>,
+    },
+    Cooldown {
+        child: Box<Node>,
+        ticks: i32,
+    },
+    PlayerInRange {
+        radius: f32,
+    },
+    HealthBelow {
+        fraction: f32,
+    },
+    MoveTo {
+        target: MoveTarget,
+        #[serde(default = "default_speed_multiplier")]
+        speed_multiplier: f32,
+        #[serde(default = "default_arrive_distance")]
+        arrive_distance: f32,
+    },
+    Shoot,
+    Spawn {
+        #[serde(default)]
+        archetype: Box<EnemyArchetype>,
+        count: u32,
+        #[serde(default)]
+        pattern: Option<SpawnPattern>,
+    },
+    Wait {
+        ticks: i32,
+    },
+}
+
+fn default_speed_multiplier() -> f32 {
+    1.
+}
+
+fn default_arrive_distance() -> f32 {
+    0.5
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum MoveTarget {
+    Player,
+    AwayFromPlayer,
+    Point { x: f32, y: f32 },
+    NearPlayer { x: f32, y: f32 },
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Status {
+    Success,
+    Failure,
+    Running,
+}
+
+pub trait Agent {
+    fn distance_to_player(&self) -> Option<f32>;
+    fn health_fraction(&self) -> f32;
+    fn move_to(
+        &mut self,
+        target: MoveTarget,
+        speed_multiplier: f32,
+        arrive_distance: f32,
+    ) -> Status;
+    fn shoot(&mut self) -> Status;
+    fn spawn(&mut self, archetype: &EnemyArchetype, count: u32, pattern: Option<SpawnPattern>);
+}
+
+#[derive(Debug)]
+pub struct BehaviorTree {
+    root: Node,
+    // состояние узлов хранится плоско, индекс узла - его номер при обходе в глубину
+    memory: Vec<i32>,
+    tick: i32,
+}
+
+impl BehaviorTree {
+    pub fn new(root: Node) -> BehaviorTree {
+        let size = root.size();
+        BehaviorTree {
+            root,
+            memory: vec![0; size],
+            tick: 0,
+        }
+    }
+
+    pub fn tick<A: Agent>(&mut self, agent: &mut A) -> Status {
+        self.tick += 1;
+        tick_node(&self.root, 0, &mut self.memory, self.tick, agent)
+    }
+}
+
+impl Node {
+    fn size(&self) -> usize {
+        1 + match self {
+            Node::Sequence { children } | Node::Selector { children } => {
+                children.iter().map(|c| c.size()).sum()
+            }
+            Node::Inverter { child }
+            | Node::Succeeder { child }
+            | Node::Repeat { child, .. }
+            | Node::Cooldown { child, .. } => child.size(),
+            _ => 0,
+        }
+    }
+}
+
+fn tick_node<A: Agent>(
+    node: &Node,
+    id: usize,
+    memory: &mut [i32],
+    now: i32,
+    agent: &mut A,
+) -> Status {
+    match node {
+        Node::Sequence { children } => {
+            tick_composite(children, Status::Success, id, memory, now, agent)
+        }
+        Node::Selector { children } => {
+            tick_composite(children, Status::Failure, id, memory, now, agent)
+        }
+        Node::Inverter { child } => match tick_node(child, id + 1, memory, now, agent) {
+            Status::Success => Status::Failure,
+            Status::Failure => Status::Success,
+            Status::Running => Status::Running,
+        },
+        Node::Succeeder { child } => match tick_node(child, id + 1, memory, now, agent) {
+            Status::Running => Status::Running,
+            _ => Status::Success,
+        },
+        Node::Repeat { child, times } => {
+            if tick_node(child, id + 1, memory, now, agent) == Status::Running {
+                return Status::Running;
+            }
+
+            memory[id] += 1;
+            match times {
+                Some(times) if memory[id] >= *times => {
+                    memory[id] = 0;
+                    Status::Success
+                }
+                _ => Status::Running,
+            }
+        }
+        Node::Cooldown { child, ticks } => {
+            if now < memory[id] {
+                return Status::Failure;
+            }
+
+            let status = tick_node(child, id + 1, memory, now, agent);
+            if status == Status::Success {
+                memory[id] = now + ticks;
+            }
+            status
+        }
+        Node::PlayerInRange { radius } => match agent.distance_to_player() {
+            Some(distance) if distance <= *radius => Status::Success,
+            _ => Status::Failure,
+        },
+        Node::HealthBelow { fraction } => match agent.health_fraction() < *fraction {
+            true => Status::Success,
+            false => Status::Failure,
+        },
+        Node::MoveTo {
+            target,
+            speed_multiplier,
+            arrive_distance,
+        } => agent.move_to(*target, *speed_multiplier, *arrive_distance),
+        Node::Shoot => agent.shoot(),
+        Node::Spawn {
+            archetype,
+            count,
+            pattern,
+        } => {
+            agent.spawn(archetype, *count, *pattern);
+            Status::Success
+        }
+        Node::Wait { ticks } => {
+            memory[id] += 1;
+            match memory[id] >= *ticks {
+                true => {
+                    memory[id] = 0;
+                    Status::Success
+                }
+                false => Status::Running,
+            }
+        }
+    }
+}
+
+// sequence идёт дальше пока дети успешны, selector - пока дети проваливаются
+fn tick_composite<A: Agent>(
+    children: &[Node],
+    continue_on: Status,
+    id: usize,
+    memory: &mut [i32],
+    now: i32,
+    agent: &mut A,
+) -> Status {
+    let mut child_id = id + 1;
+    let first = memory[id] as usize;
+    for child in children.iter().take(first) {
+        child_id += child.size();
+    }
+
+    for (index, child) in children.iter().enumerate().skip(first) {
+        let status = tick_node(child, child_id, memory, now, agent);
+        if status == Status::Running {
+            memory[id] = index as i32;
+            return Status::Running;
+        }
+        if status != continue_on {
+            memory[id] = 0;
+            return status;
+        }
+        child_id += child.size();
+    }
+
+    memory[id] = 0;
+    continue_on
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct TestAgent {
+        distance: f32,
+        shots: u32,
+        moves: u32,
+    }
+
+    impl Agent for TestAgent {
+        fn distance_to_player(&self) -> Option<f32> {
+            Some(self.distance)
+        }
+
+        fn health_fraction(&self) -> f32 {
+            1.
+        }
+
+        fn move_to(&mut self, _: MoveTarget, _: f32, _: f32) -> Status {
+            self.moves += 1;
+            Status::Running
+        }
+
+        fn shoot(&mut self) -> Status {
+            self.shots += 1;
+            Status::Success
+        }
+
+        fn spawn(&mut self, _: &EnemyArchetype, _: u32, _: Option<SpawnPattern>) {}
+    }
+
+    #[test]
+    fn shoot_in_range_otherwise_approach_test() {
+        let root: Node = serde_json::from_str(
+            r#"{
+                "type": "Selector",
+                "children": [
+                    {
+                        "type": "Sequence",
+                        "children": [
+                            { "type": "PlayerInRange", "radius": 5 },
+                            { "type": "Cooldown", "ticks": 3, "child": { "type": "Shoot" } }
+                        ]
+                    },
+                    { "type": "MoveTo", "target": { "type": "Player" } }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut tree = BehaviorTree::new(root);
+
+        let mut agent = TestAgent {
+            distance: 10.,
+            ..TestAgent::default()
+        };
+        assert_eq!(Status::Running, tree.tick(&mut agent));
+        assert_eq!(1, agent.moves);
+
+        // пока идёт движение selector помнит бегущую ветку
+        agent.distance = 2.;
+        tree.tick(&mut agent);
+        assert_eq!(2, agent.moves);
+    }
+
+    #[test]
+    fn cooldown_and_wait_test() {
+        let root: Node = serde_json::from_str(
+            r#"{
+                "type": "Sequence",
+                "children": [
+                    { "type": "Cooldown", "ticks": 5, "child": { "type": "Shoot" } },
+                    { "type": "Wait", "ticks": 2 }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut tree = BehaviorTree::new(root);
+        let mut agent = TestAgent::default();
+
+        let statuses = (0..6).map(|_| tree.tick(&mut agent)).collect::<Vec<_>>();
+
+        assert_eq!(
+            vec![
+                Status::Running,
+                Status::Success,
+                Status::Failure,
+                Status::Failure,
+                Status::Failure,
+                Status::Running,
+            ],
+            statuses
+        );
+        assert_eq!(2, agent.shots);
+    }
+}
@@ -1,3 +1,6 @@
+use crate::behavior::{self, BehaviorTree, MoveTarget};
+use crate::flow::FlowField;
+use crate::level::{Level, WaveTrigger, WinCondition};
 use crate::math;
 use core::cmp::Ordering;
 use na::geometry::*;
@@ -5,10 +8,12 @@ use na::Vector2;
 use rand::rngs::SmallRng;
 use rand::Rng;
 use rand::SeedableRng;
+use serde_derive::Deserialize;
 use specs::{
-    Builder, Component, Dispatcher, DispatcherBuilder, NullStorage, Read, ReadStorage, System,
-    VecStorage, Write, WriteStorage,
+    Builder, Component, Dispatcher, DispatcherBuilder, Entity, LazyUpdate, NullStorage, Read,
+    ReadStorage, System, VecStorage, Write, WriteStorage,
 };
+use std::collections::HashMap;
 
 type Point = Point2<f32>;
 type Vector = Vector2<f32>;
@@ -16,14 +21,17 @@ type Vector = Vector2<f32>;
 pub struct MainState {
     pub spec_world: specs::World,
     dispatcher: Dispatcher<'static, 'static>,
-    settings: Settings,
-    rnd: SmallRng,
 }
 
 pub struct Input {
     player_direction: Vector,
     shoot_point: Option<Point>,
     shoot_force: i32,
+    bomb_target: Option<Point>,
+    switch_polarity: bool,
+    use_ultimate: bool,
+    dash: bool,
+    turret_point: Option<Point>,
 }
 
 impl Default for Input {
@@ -31,729 +39,7457 @@ impl Default for Input {
         Input {
             player_direction: Vector::zeros(),
             shoot_point: None,
-            shoot_force: 0,
+            shoot_force: 1,
+            bomb_target: None,
+            switch_polarity: false,
+            use_ultimate: false,
+            dash: false,
+            turret_point: None,
         }
     }
 }
 
 pub struct Settings {
-    world_size: Point,
+    boundary: Boundary,
     fps: i32,
     gun_reload_ticks: i32,
+    spawn_safe_distance: f32,
+    spawn_telegraph_ticks: i32,
+    player_health: i32,
+    movement: MovementConfig,
     rnd: SmallRng,
 }
 
 impl Default for Settings {
     fn default() -> Self {
         Settings {
-            world_size: Point::new(10., 10.),
+            boundary: Boundary::new(ArenaShape::Rectangle, Point::new(10., 10.), None),
             fps: 50,
             gun_reload_ticks: 10,
+            player_health: 5,
+            spawn_safe_distance: 5.,
+            spawn_telegraph_ticks: 50,
+            movement: MovementConfig::default(),
             rnd: SmallRng::seed_from_u64(1),
         }
     }
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Player {
-    max_speed: f32,
-    pub radius: f32,
-}
-
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Enemy {
-    pub radius: f32,
-    max_speed: f32,
+// скорость в клетках в секунду, ускорения в клетках в секунду за секунду
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct MovementConfig {
+    pub max_speed: f32,
+    pub acceleration: f32,
+    pub deceleration: f32,
+    pub response_curve: f32,
+    pub dead_zone: f32,
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Gun {
-    tick_to_reload: i32,
+impl Default for MovementConfig {
+    fn default() -> Self {
+        MovementConfig {
+            max_speed: 6.,
+            acceleration: 60.,
+            deceleration: 80.,
+            response_curve: 1.,
+            dead_zone: 0.15,
+        }
+    }
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Shot {
-    from: Point,
-    to: Point,
-    force: i32,
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum ArenaShape {
+    Rectangle,
+    Circle,
+    // вышедший за край возвращается с противоположной стороны
+    Wrap,
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct DeadByTtl {
-    ttl: i32,
+// после задержки арена сжимается к центру на долю своего размера в секунду
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ShrinkConfig {
+    pub delay_ticks: i32,
+    pub rate: f32,
+    pub min_scale: f32,
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct ShotTrace {
-    pub from: Point,
-    pub to: Point,
+impl Default for ShrinkConfig {
+    fn default() -> Self {
+        ShrinkConfig {
+            delay_ticks: 500,
+            rate: 0.02,
+            min_scale: 0.4,
+        }
+    }
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Color {
-    pub is_white: bool,
-    pub damage: i32,
+// круглая арена вписана в прямоугольник мира, сжатие уменьшает любую форму
+#[derive(Clone, Copy, Debug)]
+pub struct Boundary {
+    pub shape: ArenaShape,
+    pub size: Point,
+    pub scale: f32,
+    shrink: Option<ShrinkConfig>,
+    tick: i32,
 }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Spawner {
-    pub tick_to_spawn: i32,
-}
+impl Boundary {
+    pub fn new(shape: ArenaShape, size: Point, shrink: Option<ShrinkConfig>) -> Boundary {
+        Boundary {
+            shape,
+            size,
+            scale: 1.,
+            shrink,
+            tick: 0,
+        }
+    }
 
-#[derive(Component, Debug, Default)]
-#[storage(NullStorage)]
-pub struct EnemyKillEvent;
+    pub fn get_center(&self) -> Point {
+        Point::new(self.size.x / 2., self.size.y / 2.)
+    }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Scope {
-    pub scope: u32,
-}
+    pub fn get_min(&self) -> Point {
+        self.get_center() - self.get_half_size()
+    }
 
-impl Enemy {
-    fn default() -> Enemy {
-        Enemy {
-            radius: 0.5,
-            max_speed: 4.,
-        }
+    pub fn get_max(&self) -> Point {
+        self.get_center() + self.get_half_size()
     }
-}
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Position {
-    pub point: Point2<f32>,
-}
+    fn get_half_size(&self) -> Vector {
+        Vector::new(self.size.x / 2., self.size.y / 2.) * self.scale
+    }
 
-#[derive(Component, Debug)]
-#[storage(VecStorage)]
-pub struct Velocity {
-    velocity: Vector,
-}
+    pub fn get_radius(&self) -> f32 {
+        self.size.x.min(self.size.y) / 2. * self.scale
+    }
 
-impl MainState {
-    pub fn new() -> MainState {
-        let mut spec_world = specs::World::new();
-        spec_world.add_resource(Settings {
-            world_size: Point::new(50., 40.),
-            fps: 50,
-            gun_reload_ticks: 5,
-            rnd: SmallRng::seed_from_u64(1),
-        });
+    fn update(&mut self, fps: i32) {
+        if let Some(shrink) = self.shrink {
+            self.tick += 1;
+            if self.tick > shrink.delay_ticks {
+                self.scale = (self.scale - shrink.rate / fps as f32).max(shrink.min_scale);
+            }
+        }
+    }
 
-        let mut dispatcher = DispatcherBuilder::new()
-            .with(RemoveByTtlSystem, "", &[])
-            .with(UpdateTtlSystem, "", &[])
-            .with(PlayerPositionSystem, "", &[])
-            .with(PlayerVelocitySystem, "", &[])
-            .with(ReturnPlayerToWarzoneSystem, "", &[])
-            .with(EnemiesVelocitySystem, "", &[])
-            .with(EnemiesPositionSystem, "", &[])
-            .with(GunShotSystem, "", &[])
-            .with(ShotSystem, "", &[])
-            .with(RemoveOvercoloredEmenySystem, "", &[])
-            .with(DamagePlayerSystem, "", &[])
-            .with(ScopeSystem, "", &[])
-            .with(SpawnEnemiesSystem, "", &[])
-            .build();
+    // возвращает круг заданного радиуса внутрь арены
+    fn confine(&self, point: &Point, radius: f32) -> Point {
+        let (min, max) = (self.get_min(), self.get_max());
+        match self.shape {
+            ArenaShape::Rectangle => Point::new(
+                point.x.max(min.x + radius).min(max.x - radius),
+                point.y.max(min.y + radius).min(max.y - radius),
+            ),
+            ArenaShape::Circle => {
+                let center = self.get_center();
+                let offset = point - center;
+                let max_distance = (self.get_radius() - radius).max(0.);
+                match offset.try_normalize(0.001) {
+                    Some(direction) if offset.norm() > max_distance => {
+                        center + direction * max_distance
+                    }
+                    _ => *point,
+                }
+            }
+            ArenaShape::Wrap => self.wrap(point),
+        }
+    }
 
-        dispatcher.setup(&mut spec_world.res);
+    fn wrap(&self, point: &Point) -> Point {
+        if self.shape != ArenaShape::Wrap {
+            return *point;
+        }
 
-        spec_world.register::<Color>();
-        spec_world.register::<Enemy>();
-        spec_world.register::<Spawner>();
-        spec_world.register::<Scope>();
+        let min = self.get_min();
+        let size = self.get_max() - min;
+        Point::new(
+            min.x + (point.x - min.x).rem_euclid(size.x),
+            min.y + (point.y - min.y).rem_euclid(size.y),
+        )
+    }
 
-        MainState {
-            spec_world: spec_world,
-            settings: Settings {
-                world_size: Point::new(50., 40.),
-                fps: 50,
-                gun_reload_ticks: 5,
-                rnd: SmallRng::seed_from_u64(1),
-            },
-            dispatcher: dispatcher,
-            rnd: SmallRng::seed_from_u64(0),
+    pub fn contains(&self, point: &Point) -> bool {
+        match self.shape {
+            ArenaShape::Circle => (point - self.get_center()).norm() <= self.get_radius(),
+            _ => {
+                let (min, max) = (self.get_min(), self.get_max());
+                point.x >= min.x && point.y >= min.y && point.x <= max.x && point.y <= max.y
+            }
         }
     }
 
-    pub fn init(self: &mut MainState) {
-        self.spec_world
-            .create_entity()
-            .with(Player {
-                max_speed: 6.,
-                radius: 0.25,
-            })
-            .with(Position {
-                point: Point2::new(
-                    self.settings.world_size.x / 2.,
-                    self.settings.world_size.y / 2.,
-                ),
-            })
-            .with(Velocity {
-                velocity: Vector2::new(0., 0.),
-            })
-            .with(Gun { tick_to_reload: 0 })
-            .build();
-
-        self.spec_world
-            .create_entity()
-            .with(Spawner { tick_to_spawn: 0 })
-            .with(Scope { scope: 0 })
-            .build();
+    // кратчайшее смещение между точками, на торе оно может проходить через край
+    pub fn get_offset(&self, from: &Point, to: &Point) -> Vector {
+        let offset = to - from;
+        if self.shape != ArenaShape::Wrap {
+            return offset;
+        }
 
-        (0..10).for_each(|_| create_enemy(&mut self.spec_world, &self.settings, &mut self.rnd));
+        let size = self.get_max() - self.get_min();
+        Vector::new(
+            offset.x - size.x * (offset.x / size.x).round(),
+            offset.y - size.y * (offset.y / size.y).round(),
+        )
     }
 
-    pub fn step(self: &mut MainState) {
-        self.dispatcher.dispatch(&mut self.spec_world.res);
-        self.spec_world.maintain();
+    // на торе цели видны и в соседних копиях мира
+    fn get_wrap_offsets(&self) -> Vec<Vector> {
+        if self.shape != ArenaShape::Wrap {
+            return vec![Vector::zeros()];
+        }
+
+        let size = self.get_max() - self.get_min();
+        let steps = [-1., 0., 1.];
+        steps
+            .iter()
+            .flat_map(|i| {
+                steps
+                    .iter()
+                    .map(move |j| Vector::new(i * size.x, j * size.y))
+            })
+            .collect()
     }
 
-    pub fn set_player_direction(self: &mut MainState, direction: &mut Vector) {
-        if direction.norm() > 1.0 {
-            direction.try_normalize_mut(0.01);
+    // на торе отрезок рвётся у края и продолжается с противоположной стороны
+    fn split_segment(&self, from: &Point, to: &Point) -> Vec<(Point, Point)> {
+        if self.shape != ArenaShape::Wrap {
+            return vec![(*from, *to)];
         }
 
-        self.spec_world.write_resource::<Input>().player_direction = direction.clone();
+        let (min, max) = (self.get_min(), self.get_max());
+        let size = max - min;
+        let mut start = self.wrap(from);
+        let mut end = start + (to - from);
+        let mut pieces = Vec::new();
+        for _ in 0..4 {
+            let ray = end - start;
+            let mut exit = 1.;
+            let mut shift = Vector::zeros();
+            for axis in 0..2 {
+                let (bound, step) = match end[axis] {
+                    e if e > max[axis] => (max[axis], -size[axis]),
+                    e if e < min[axis] => (min[axis], size[axis]),
+                    _ => continue,
+                };
+                let t = (bound - start[axis]) / ray[axis];
+                if t < exit {
+                    exit = t;
+                    shift = Vector::zeros();
+                    shift[axis] = step;
+                }
+            }
+
+            let cut = start + ray * exit;
+            pieces.push((start, cut));
+            if exit >= 1. {
+                break;
+            }
+            start = cut + shift;
+            end += shift;
+        }
+        pieces
     }
 
-    pub fn set_shoot_point(self: &mut MainState, shoot_point: Option<Point>) {
-        self.spec_world.write_resource::<Input>().shoot_point = shoot_point;
+    // случайная точка на границе арены и направление вдоль границы
+    fn get_random_edge_point<R: Rng>(&self, rnd: &mut R) -> (Point, Vector) {
+        if self.shape == ArenaShape::Circle {
+            let angle = rnd.gen::<f32>() * std::f32::consts::PI * 2.;
+            let (sin, cos) = angle.sin_cos();
+            return (
+                self.get_center() + Vector::new(cos, sin) * self.get_radius(),
+                Vector::new(-sin, cos),
+            );
+        }
+
+        let min = self.get_min();
+        let size = self.get_max() - min;
+        if rnd.gen() {
+            (
+                Point::new(
+                    min.x + size.x * (rnd.gen::<u32>() % 2) as f32,
+                    min.y + size.y * rnd.gen::<f32>(),
+                ),
+                Vector::new(0., 1.),
+            )
+        } else {
+            (
+                Point::new(
+                    min.x + size.x * rnd.gen::<f32>(),
+                    min.y + size.y * (rnd.gen::<u32>() % 2) as f32,
+                ),
+                Vector::new(1., 0.),
+            )
+        }
     }
 
-    pub fn set_shoot_force(self: &mut MainState, force: i32) {
-        self.spec_world.write_resource::<Input>().shoot_force = force;
+    // углы арены и направления внутрь, у круга это точки на диагоналях
+    fn get_corners(&self) -> [(Point, Vector); 4] {
+        let (min, max) = (self.get_min(), self.get_max());
+        let mut corners = [
+            (min, Vector::new(1., 1.)),
+            (Point::new(max.x, min.y), Vector::new(-1., 1.)),
+            (Point::new(min.x, max.y), Vector::new(1., -1.)),
+            (max, Vector::new(-1., -1.)),
+        ];
+        if self.shape == ArenaShape::Circle {
+            for (corner, inward) in corners.iter_mut() {
+                *corner = self.get_center() - inward.normalize() * self.get_radius();
+            }
+        }
+        corners
     }
 }
 
-struct ScopeSystem;
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum SpawnPattern {
+    Edges,
+    Corners,
+    Ring { radius: f32 },
+    Line { length: f32 },
+    Cluster,
+    At { x: f32, y: f32 },
+}
 
-impl<'a> System<'a> for ScopeSystem {
-    type SystemData = (
-        WriteStorage<'a, Scope>,
-        ReadStorage<'a, EnemyKillEvent>,
-        specs::Entities<'a>,
-    );
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum EnemyColor {
+    White,
+    Black,
+    Random,
+}
 
-    fn run(&mut self, (mut scope_storage, kill_event_storage, entities): Self::SystemData) {
-        use specs::Join;
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct EnemyArchetype {
+    pub radius: f32,
+    pub max_speed: f32,
+    pub color: EnemyColor,
+    pub health: Option<i32>,
+    pub boss: Option<BossDef>,
+    pub steering: Steering,
+    pub ai: Option<AiConfig>,
+    pub behavior: Option<behavior::Node>,
+    pub gun: Option<GunConfig>,
+    pub swarm: bool,
+    pub explosive: Option<ExplosiveConfig>,
+    pub drop_chance: f32,
+    pub drops: Vec<PickupKind>,
+}
 
-        let scope = (&mut scope_storage).join().next();
-        let kills = (&entities, &kill_event_storage)
-            .join()
-            .map(|(e, _)| e)
-            .collect::<Vec<_>>();
+impl Default for EnemyArchetype {
+    fn default() -> Self {
+        EnemyArchetype {
+            radius: 0.5,
+            max_speed: 4.,
+            color: EnemyColor::Random,
+            health: None,
+            boss: None,
+            steering: Steering::default(),
+            ai: None,
+            behavior: None,
+            gun: None,
+            swarm: false,
+            explosive: None,
+            drop_chance: 0.,
+            drops: vec![],
+        }
+    }
+}
 
-        if let Some(s) = scope {
-            s.scope += kills.len() as u32;
+impl EnemyArchetype {
+    fn get_is_white<R: Rng>(&self, rnd: &mut R) -> bool {
+        let boss_phase = self.boss.as_ref().and_then(|b| b.phases.first());
+        if let Some(phase) = boss_phase {
+            return phase.is_white;
         }
 
-        for e in kills {
-            entities.delete(e).unwrap();
+        match self.color {
+            EnemyColor::White => true,
+            EnemyColor::Black => false,
+            EnemyColor::Random => rnd.gen::<u32>() % 2 == 0,
         }
     }
 }
 
-struct DamagePlayerSystem;
+#[derive(Clone, Debug, Deserialize)]
+pub struct BossDef {
+    pub name: String,
+    pub phases: Vec<BossPhase>,
+}
 
-impl<'a> System<'a> for DamagePlayerSystem {
-    type SystemData = (
-        ReadStorage<'a, Position>,
-        ReadStorage<'a, Player>,
-        ReadStorage<'a, Enemy>,
-        specs::Entities<'a>,
-    );
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct BossPhase {
+    pub health_threshold: f32,
+    pub is_white: bool,
+    pub max_speed: Option<f32>,
+    pub weak_point: Option<WeakPoint>,
+    pub minions: Option<MinionSpawn>,
+    pub attack: Option<BossAttack>,
+}
 
-    fn run(&mut self, (pos_storage, player_storage, enemy_storage, entities): Self::SystemData) {
-        use specs::Join;
+impl Default for BossPhase {
+    fn default() -> Self {
+        BossPhase {
+            health_threshold: 1.,
+            is_white: true,
+            max_speed: None,
+            weak_point: None,
+            minions: None,
+            attack: None,
+        }
+    }
+}
 
-        let player = (&entities, &pos_storage, &player_storage).join().next();
+// угол слабого места отсчитывается от направления на игрока
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct WeakPoint {
+    pub angle: f32,
+    pub arc: f32,
+    pub damage_multiplier: i32,
+}
 
-        if let Some((entity, p_pos, p)) = player {
-            let any_collision = (&pos_storage, &enemy_storage).join().any(|(e_pos, e)| {
-                has_circles_collision(&e_pos.point, &p_pos.point, p.radius + e.radius)
-            });
+#[derive(Clone, Debug, Deserialize)]
+pub struct MinionSpawn {
+    #[serde(default)]
+    pub archetype: EnemyArchetype,
+    pub count: u32,
+    pub interval: i32,
+}
 
-            if any_collision {
-                entities.delete(entity).unwrap();
-            }
-        }
-    }
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum BossAttack {
+    Charge {
+        speed_multiplier: f32,
+        duration: i32,
+        interval: i32,
+    },
 }
 
-struct SpawnEnemiesSystem;
+#[derive(Debug)]
+pub struct SpawnRequest {
+    pub pattern: SpawnPattern,
+    pub count: u32,
+    pub archetype: EnemyArchetype,
+    pub spawner: Option<Entity>,
+    pub formation: Option<FormationDef>,
+}
 
-impl<'a> System<'a> for SpawnEnemiesSystem {
-    type SystemData = (
-        WriteStorage<'a, Position>,
-        WriteStorage<'a, Enemy>,
-        WriteStorage<'a, Velocity>,
-        WriteStorage<'a, Scope>,
-        WriteStorage<'a, Color>,
-        specs::Entities<'a>,
-        Write<'a, Settings>,
-    );
+#[derive(Default)]
+pub struct SpawnQueue {
+    requests: Vec<SpawnRequest>,
+    next_group: u32,
+}
 
-    fn run(
+impl SpawnQueue {
+    pub fn push(&mut self, pattern: SpawnPattern, count: u32, archetype: EnemyArchetype) {
+        self.requests.push(SpawnRequest {
+            pattern,
+            count,
+            archetype,
+            spawner: None,
+            formation: None,
+        });
+    }
+
+    pub fn push_in_formation(
         &mut self,
-        (
-            mut pos_storage,
-            mut enemy_storage,
-            mut vel_storage,
-            scope_storage,
-            mut color_storage,
-            entities,
-            mut settings,
-        ): Self::SystemData,
+        pattern: SpawnPattern,
+        count: u32,
+        archetype: EnemyArchetype,
+        formation: FormationDef,
     ) {
-        use specs::Join;
+        self.requests.push(SpawnRequest {
+            pattern,
+            count,
+            archetype,
+            spawner: None,
+            formation: Some(formation),
+        });
+    }
 
-        let maybe_scope = (&scope_storage).join().next();
-        if let Some(scope) = maybe_scope {
-            let target_count = scope.scope / 5 + 10;
-            let current_count = enemy_storage.count() as u32;
-            if current_count < target_count {
-                let position = if settings.rnd.gen() {
-                    Point::new(
-                        settings.world_size.x * (settings.rnd.gen::<u32>() % 2) as f32,
-                        settings.world_size.y * settings.rnd.gen::<f32>(),
-                    )
-                } else {
-                    Point::new(
-                        settings.world_size.x * settings.rnd.gen::<f32>(),
-                        settings.world_size.y * (settings.rnd.gen::<u32>() % 2) as f32,
-                    )
-                };
+    pub fn push_from_spawner(&mut self, spawner: Entity, point: Point, archetype: EnemyArchetype) {
+        self.requests.push(SpawnRequest {
+            pattern: SpawnPattern::At {
+                x: point.x,
+                y: point.y,
+            },
+            count: 1,
+            archetype,
+            spawner: Some(spawner),
+            formation: None,
+        });
+    }
 
-                entities
-                    .build_entity()
-                    .with(Enemy::default(), &mut enemy_storage)
-                    .with(Position { point: position }, &mut pos_storage)
-                    .with(
-                        Velocity {
-                            velocity: Vector::zeros(),
-                        },
-                        &mut vel_storage,
-                    )
-                    .with(
-                        Color {
-                            is_white: settings.rnd.gen::<u32>() % 2 == 0,
-                            damage: 0,
-                        },
-                        &mut color_storage,
-                    )
-                    .build();
-            }
-        }
+    pub fn pending_count(&self) -> u32 {
+        self.requests.iter().map(|r| r.count).sum()
     }
 }
 
-fn create_enemy<R: rand::Rng>(world: &mut specs::World, settings: &Settings, rnd: &mut R) {
-    let position = if rnd.gen() {
-        Point::new(
-            settings.world_size.x * (rnd.gen::<u32>() % 2) as f32,
-            settings.world_size.y * rnd.gen::<f32>(),
-        )
-    } else {
-        Point::new(
-            settings.world_size.x * rnd.gen::<f32>(),
-            settings.world_size.y * (rnd.gen::<u32>() % 2) as f32,
-        )
-    };
-
-    world
-        .create_entity()
-        .with(Enemy::default())
-        .with(Position { point: position })
-        .with(Velocity {
-            velocity: Vector::zeros(),
-        })
-        .with(Color {
-            is_white: rnd.gen::<u32>() % 2 == 0,
-            damage: 0,
-        })
-        .build();
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Player {
+    max_speed: f32,
+    pub radius: f32,
 }
 
-struct UpdateTtlSystem;
-
-impl<'a> System<'a> for UpdateTtlSystem {
-    type SystemData = (WriteStorage<'a, DeadByTtl>);
-
-    fn run(&mut self, mut ttl_storage: Self::SystemData) {
-        use specs::Join;
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Enemy {
+    pub radius: f32,
+    max_speed: f32,
+}
 
-        (&mut ttl_storage).join().for_each(|d| d.ttl -= 1);
-    }
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Gun {
+    tick_to_reload: i32,
+    pub config: GunConfig,
+    aim: Option<Point>,
+    charge: i32,
+    burst_left: u32,
+    tick_to_burst: i32,
+    pub ammo: Option<u32>,
+    tick_to_magazine: i32,
+    pub heat: f32,
+    tick_to_cool: i32,
 }
 
-struct RemoveByTtlSystem;
+impl Gun {
+    pub fn new(config: GunConfig) -> Gun {
+        Gun {
+            tick_to_reload: 0,
+            config,
+            aim: None,
+            charge: 0,
+            burst_left: 0,
+            tick_to_burst: 0,
+            ammo: config.magazine,
+            tick_to_magazine: 0,
+            heat: 0.,
+            tick_to_cool: 0,
+        }
+    }
 
-impl<'a> System<'a> for RemoveByTtlSystem {
-    type SystemData = (WriteStorage<'a, DeadByTtl>, specs::Entities<'a>);
+    // перезарядка и остывание идут каждый тик, даже когда курок отпущен
+    fn update(&mut self) {
+        if self.tick_to_reload > 0 {
+            self.tick_to_reload -= 1;
+        }
 
-    fn run(&mut self, (ttl_storage, entities): Self::SystemData) {
-        use specs::Join;
+        if self.tick_to_magazine > 0 {
+            self.tick_to_magazine -= 1;
+            if self.tick_to_magazine == 0 {
+                self.ammo = self.config.magazine;
+            }
+        }
 
-        (&entities, &ttl_storage)
-            .join()
-            .filter(|(_, d)| d.ttl <= 0)
-            .for_each(|(e, _)| {
-                entities.delete(e).unwrap();
-            });
+        if self.tick_to_cool > 0 {
+            self.tick_to_cool -= 1;
+        }
+        self.heat = (self.heat - self.config.cooling).max(0.);
     }
-}
-
-struct ReturnPlayerToWarzoneSystem;
 
-impl<'a> System<'a> for ReturnPlayerToWarzoneSystem {
-    type SystemData = (
-        WriteStorage<'a, Position>,
-        ReadStorage<'a, Player>,
-        specs::Read<'a, Settings>,
-    );
+    fn is_jammed(&self) -> bool {
+        self.tick_to_magazine > 0 || self.tick_to_cool > 0 || self.ammo == Some(0)
+    }
 
-    fn run(&mut self, (mut pos_storage, player_storage, settings): Self::SystemData) {
-        use specs::Join;
+    fn is_ready(&self) -> bool {
+        self.tick_to_reload == 0 && !self.is_jammed()
+    }
 
-        for (pos, pl) in (&mut pos_storage, &player_storage).join() {
-            if pos.point.y - pl.radius < 0. {
-                pos.point.y = pl.radius;
+    fn on_fired(&mut self) {
+        if self.config.heat_per_shot > 0. {
+            self.heat = (self.heat + self.config.heat_per_shot).min(1.);
+            if self.heat >= 1. {
+                self.tick_to_cool = self.config.overheat_ticks;
             }
+        }
 
-            if pos.point.x - pl.radius < 0. {
-                pos.point.x = pl.radius;
+        if let Some(ammo) = self.ammo.as_mut() {
+            *ammo = ammo.saturating_sub(1);
+            if *ammo == 0 {
+                self.reload();
             }
+        }
+    }
 
-            if pos.point.y + pl.radius > settings.world_size.y {
-                pos.point.y = settings.world_size.y - pl.radius;
-            }
+    pub fn reload(&mut self) {
+        if self.config.magazine.is_none()
+            || self.tick_to_magazine > 0
+            || self.ammo == self.config.magazine
+        {
+            return;
+        }
 
-            if pos.point.x + pl.radius > settings.world_size.x {
-                pos.point.x = settings.world_size.x - pl.radius;
-            }
+        match self.config.magazine_reload_ticks {
+            0 => self.ammo = self.config.magazine,
+            ticks => self.tick_to_magazine = ticks,
         }
     }
-}
 
-struct PlayerVelocitySystem;
-impl<'a> System<'a> for PlayerVelocitySystem {
-    type SystemData = (
-        WriteStorage<'a, Velocity>,
-        ReadStorage<'a, Player>,
-        specs::Read<'a, Input>,
-        specs::Read<'a, Settings>,
-    );
+    pub fn get_reload_progress(&self) -> Option<f32> {
+        match self.tick_to_magazine {
+            0 => None,
+            ticks => Some(1. - ticks as f32 / self.config.magazine_reload_ticks.max(1) as f32),
+        }
+    }
 
-    fn run(&mut self, (mut vel_storage, player_storage, input, settings): Self::SystemData) {
-        use specs::Join;
+    pub fn is_overheated(&self) -> bool {
+        self.tick_to_cool > 0
+    }
 
-        for (vel, player) in (&mut vel_storage, &player_storage).join() {
-            vel.velocity = input.player_direction * player.max_speed / settings.fps as f32;
+    pub fn get_charge_level(&self) -> f32 {
+        match self.config.charge_ticks {
+            0 => 0.,
+            ticks => self.charge as f32 / ticks as f32,
         }
     }
 }
 
-struct PlayerPositionSystem;
-
-impl<'a> System<'a> for PlayerPositionSystem {
-    type SystemData = (
-        WriteStorage<'a, Position>,
-        ReadStorage<'a, Velocity>,
-        ReadStorage<'a, Player>,
-    );
-
-    fn run(&mut self, (mut pos_storage, vel_storage, player_storage): Self::SystemData) {
-        use specs::Join;
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct GunConfig {
+    pub reload_ticks: i32,
+    pub range: f32,
+    pub aim_error: f32,
+    pub element: GunElement,
+    pub damage: i32,
+    pub recolor_player: bool,
+    pub projectile: Option<ProjectileConfig>,
+    pub pellets: u32,
+    pub spread: f32,
+    pub pierce: u32,
+    pub ricochet: u32,
+    pub burst: u32,
+    pub burst_interval: i32,
+    pub charge_ticks: i32,
+    pub charge_multiplier: i32,
+    pub charge_pierce: u32,
+    pub charge_radius: f32,
+    pub charge_blast_radius: f32,
+    pub shot_radius: f32,
+    pub blast_radius: f32,
+    pub magazine: Option<u32>,
+    pub magazine_reload_ticks: i32,
+    pub heat_per_shot: f32,
+    pub cooling: f32,
+    pub overheat_ticks: i32,
+    pub lock_ticks: i32,
+    pub max_locks: usize,
+    pub status: Option<StatusKind>,
+    pub status_ticks: i32,
+}
 
-        for (p, v, _) in (&mut pos_storage, &vel_storage, &player_storage).join() {
-            p.point += v.velocity;
+impl Default for GunConfig {
+    fn default() -> Self {
+        GunConfig {
+            reload_ticks: 25,
+            range: 12.,
+            aim_error: 0.,
+            element: GunElement::Own,
+            damage: 1,
+            recolor_player: false,
+            projectile: None,
+            pellets: 1,
+            spread: 0.,
+            pierce: 0,
+            ricochet: 0,
+            burst: 1,
+            burst_interval: 3,
+            charge_ticks: 0,
+            charge_multiplier: 3,
+            charge_pierce: 0,
+            charge_radius: 0.,
+            charge_blast_radius: 0.,
+            shot_radius: 0.,
+            blast_radius: 0.,
+            magazine: None,
+            magazine_reload_ticks: 60,
+            heat_per_shot: 0.,
+            cooling: 0.01,
+            overheat_ticks: 75,
+            lock_ticks: 0,
+            max_locks: 4,
+            status: None,
+            status_ticks: 100,
         }
     }
 }
 
-struct EnemiesVelocitySystem;
-impl<'a> System<'a> for EnemiesVelocitySystem {
-    type SystemData = (
-        ReadStorage<'a, Position>,
-        ReadStorage<'a, Player>,
-        ReadStorage<'a, Enemy>,
-        WriteStorage<'a, Velocity>,
-        Read<'a, Settings>,
-    );
+impl GunConfig {
+    // чем дольше копился заряд, тем шире, пробивнее и разрушительнее выстрел
+    fn charged(&self, level: f32) -> GunConfig {
+        GunConfig {
+            pierce: self.pierce + (self.charge_pierce as f32 * level) as u32,
+            shot_radius: self.shot_radius + self.charge_radius * level,
+            blast_radius: self.blast_radius + self.charge_blast_radius * level,
+            ..*self
+        }
+    }
 
-    fn run(
-        &mut self,
-        (pos_storage, player_storage, enemy_storage, mut vel_storage, settings): Self::SystemData,
-    ) {
-        use specs::Join;
+    fn get_charged_force(&self, force: i32, level: f32) -> i32 {
+        force * (1 + ((self.charge_multiplier - 1) as f32 * level).round() as i32)
+    }
+}
 
-        let player = (&pos_storage, &player_storage).join().next();
+// медленный снаряд вместо мгновенного луча, скорость в клетках в секунду
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ProjectileConfig {
+    pub speed: f32,
+    pub lifetime: i32,
+    pub radius: f32,
+    // самонаведение, радиан в секунду
+    pub turn_rate: f32,
+}
 
-        if let Some((p_pos, _)) = player {
-            for (e_pos, e_vel, e) in (&pos_storage, &mut vel_storage, &enemy_storage).join() {
-                let direction = (p_pos.point - e_pos.point).try_normalize(0.001);
-                e_vel.velocity = match direction {
-                    Some(d) => d * e.max_speed / settings.fps as f32,
-                    None => Vector::zeros(),
-                }
-            }
+impl Default for ProjectileConfig {
+    fn default() -> Self {
+        ProjectileConfig {
+            speed: 10.,
+            lifetime: 150,
+            radius: 0.2,
+            turn_rate: 0.,
         }
     }
 }
 
-struct EnemiesPositionSystem;
-impl<'a> System<'a> for EnemiesPositionSystem {
-    type SystemData = (
-        WriteStorage<'a, Position>,
-        ReadStorage<'a, Enemy>,
-        ReadStorage<'a, Velocity>,
-    );
-
-    fn run(&mut self, (mut pos_storage, enemy_storage, vel_storage): Self::SystemData) {
-        use specs::Join;
-        let mut enemies = (&mut pos_storage, &vel_storage, &enemy_storage)
-            .join()
-            .collect::<Vec<_>>();
-
-        for enemy_id in 0..enemies.len() {
-            let maybe_pos = {
-                let (e_pos, e_vel, enemy) = &enemies[enemy_id];
-                let new_pos = e_pos.point + e_vel.velocity;
-
-                let has_collision = enemies.iter().any(|(ae_pos, _, e)| {
-                    std::ptr::eq(e, enemy) == false
-                        && has_circles_collision(&ae_pos.point, &new_pos, e.radius + &enemy.radius)
-                });
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct HomingTarget {
+    pub target: Option<Entity>,
+    turn_rate: f32,
+}
 
-                match has_collision {
-                    true => None,
-                    false => Some(new_pos),
-                }
-            };
+// пока прицел держится на враге, он попадает в захват
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct LockOn {
+    pub targets: Vec<Entity>,
+    pub aimed: Option<Entity>,
+    pub aim_ticks: i32,
+}
 
-            if let Some(pos) = maybe_pos {
-                enemies[enemy_id].0.point = pos;
-            }
+impl LockOn {
+    // ракеты разбирают захваченные цели по очереди, захват уходит вместе с ракетой
+    fn next_target(&mut self) -> Option<Entity> {
+        if self.targets.is_empty() {
+            return None;
         }
+        self.aim_ticks = 0;
+        Some(self.targets.remove(0))
     }
 }
 
-struct GunShotSystem;
-impl<'a> System<'a> for GunShotSystem {
-    type SystemData = (
-        specs::Entities<'a>,
-        WriteStorage<'a, Gun>,
-        WriteStorage<'a, Shot>,
-        WriteStorage<'a, DeadByTtl>,
-        ReadStorage<'a, Position>,
-        specs::Read<'a, Input>,
-        specs::Read<'a, Settings>,
-    );
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Projectile {
+    velocity: Vector,
+    pub radius: f32,
+    force: i32,
+    owner: Option<Entity>,
+    // сторона запоминается при выстреле, снаряды погибшего врага остаются вражескими
+    pub is_hostile: bool,
+    recolor: bool,
+    blast_radius: f32,
+    status: Option<(StatusKind, i32)>,
+}
 
-    fn run(
-        &mut self,
-        (
-            entities,
-            mut gun_storage,
-            mut shot_storage,
-            mut ttl_storage,
-            pos_storage,
-            input,
-            settings,
-        ): Self::SystemData,
-    ) {
-        use specs::Join;
+impl Projectile {
+    pub fn is_white(&self) -> bool {
+        self.force > 0
+    }
+}
 
-        if let Some(shoot_point) = input.shoot_point {
-            let shots = (&mut gun_storage, &pos_storage)
-                .join()
-                .filter_map(|(gun, pos)| {
-                    let shot = match gun.tick_to_reload {
-                        0 => Some(Shot {
-                            from: pos.point,
-                            to: shoot_point,
-                            force: input.shoot_force,
-                        }),
-                        _ => None,
-                    };
+// цвет выстрела: свой цвет владельца или заданный
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum GunElement {
+    Own,
+    White,
+    Black,
+}
 
-                    gun.tick_to_reload = match gun.tick_to_reload {
-                        x if x > 0 => x - 1,
-                        _ => settings.gun_reload_ticks,
-                    };
+#[derive(Clone, Debug, Deserialize)]
+pub struct WeaponDef {
+    pub name: String,
+    #[serde(default)]
+    pub gun: GunConfig,
+}
 
-                    shot
-                })
-                .collect::<Vec<_>>();
+// набор оружия игрока, текущее стоит в его Gun, остальные ждут в кобуре со своим магазином и нагревом
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Arsenal {
+    pub weapons: Vec<WeaponDef>,
+    pub current: usize,
+    holstered: Vec<Gun>,
+}
 
-            for shot in shots {
-                entities
-                    .build_entity()
-                    .with(shot, &mut shot_storage)
-                    .with(DeadByTtl { ttl: 0 }, &mut ttl_storage)
-                    .build();
-            }
+impl Arsenal {
+    pub fn new(weapons: Vec<WeaponDef>) -> Arsenal {
+        Arsenal {
+            holstered: weapons.iter().map(|w| Gun::new(w.gun)).collect(),
+            weapons,
+            current: 0,
         }
     }
+
+    // смена оружия не сбрасывает идущую перезарядку
+    fn select(&mut self, index: usize, gun: &mut Gun) {
+        if index == self.current || index >= self.holstered.len() {
+            return;
+        }
+
+        let tick_to_reload = gun.tick_to_reload;
+        std::mem::swap(gun, &mut self.holstered[self.current]);
+        std::mem::swap(gun, &mut self.holstered[index]);
+        gun.tick_to_reload = gun.tick_to_reload.max(tick_to_reload);
+        self.current = index;
+    }
 }
 
-struct ShotSystem;
-impl<'a> System<'a> for ShotSystem {
-    type SystemData = (
-        specs::Entities<'a>,
-        ReadStorage<'a, Shot>,
-        WriteStorage<'a, Color>,
-        ReadStorage<'a, Position>,
-        ReadStorage<'a, Enemy>,
-        WriteStorage<'a, DeadByTtl>,
-        WriteStorage<'a, ShotTrace>,
-    );
+fn get_default_weapons(reload_ticks: i32) -> Vec<WeaponDef> {
+    let weapon = |name: &str, gun| WeaponDef {
+        name: name.to_string(),
+        gun,
+    };
+    vec![
+        weapon(
+            "pistol",
+            GunConfig {
+                reload_ticks,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "shotgun",
+            GunConfig {
+                reload_ticks: 20,
+                pellets: 5,
+                spread: 0.5,
+                magazine: Some(6),
+                magazine_reload_ticks: 75,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "beam",
+            GunConfig {
+                reload_ticks: 15,
+                pierce: 3,
+                heat_per_shot: 0.25,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "ricochet",
+            GunConfig {
+                reload_ticks: 12,
+                ricochet: 2,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "burst",
+            GunConfig {
+                reload_ticks: 20,
+                burst: 3,
+                burst_interval: 3,
+                magazine: Some(30),
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "charge",
+            GunConfig {
+                reload_ticks: 10,
+                charge_ticks: 40,
+                charge_multiplier: 3,
+                charge_pierce: 2,
+                charge_radius: 0.3,
+                charge_blast_radius: 2.,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "missiles",
+            GunConfig {
+                reload_ticks: 20,
+                damage: 2,
+                projectile: Some(ProjectileConfig {
+                    speed: 8.,
+                    lifetime: 250,
+                    radius: 0.25,
+                    turn_rate: 4.,
+                }),
+                lock_ticks: 15,
+                ..GunConfig::default()
+            },
+        ),
+        weapon(
+            "frost",
+            GunConfig {
+                reload_ticks: 15,
+                status: Some(StatusKind::Slow),
+                status_ticks: 75,
+                ..GunConfig::default()
+            },
+        ),
+    ]
+}
 
-    fn run(
-        &mut self,
-        (
-            entities,
-            shot_storage,
-            mut color_storage,
-            pos_storage,
-            enemy_storage,
-            mut ttl_storage,
-            mut trace_storage,
-        ): Self::SystemData,
-    ) {
-        use specs::Join;
+// оружие с курком стреляет не по вводу игрока, а туда, куда его направил ИИ
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct Trigger {
+    pub target: Option<Point>,
+}
 
-        let shots = (&shot_storage).join().collect::<Vec<_>>();
+// по какому правилу союзник выбирает цель среди врагов в радиусе своего оружия
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum TargetRule {
+    Nearest,
+    MatchingColor,
+    LowestHealth,
+}
 
-        let mut traces = Vec::new();
-        for shot in shots {
-            let enemies = (&enemy_storage, &pos_storage, &mut color_storage)
-                .join()
-                .collect::<Vec<_>>();
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AllyKind {
+    Drone,
+    Turret,
+}
 
-            let mut enemies_hits = vec![];
-            for (enemy, enemy_pos, color) in enemies {
-                let hit = get_enemy_hit_point(&shot, &enemy, &enemy_pos.point);
-                if let Some(hit_pos) = hit {
-                    enemies_hits.push((color, hit_pos));
-                }
-            }
+// союзник стреляет сам через свой курок, цвет выстрела берёт у игрока
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Ally {
+    pub kind: AllyKind,
+    pub rule: TargetRule,
+    pub target: Option<Entity>,
+}
 
-            enemies_hits.sort_by(|(_, a), (_, b)| {
-                compare_vector_lengths(&(a - shot.from), &(b - shot.from))
-            });
+// дрон кружит вокруг игрока, угловая скорость в радианах в секунду
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Orbit {
+    pub angle: f32,
+    radius: f32,
+    angular_speed: f32,
+}
 
-            let enemy_hit = enemies_hits.first_mut();
-            if let Some((color, _)) = enemy_hit {
-                color.damage = shot.force;
-            }
+const DRONE_ORBIT_RADIUS: f32 = 1.5;
+const DRONE_ANGULAR_SPEED: f32 = 2.;
+const MAX_TURRETS: usize = 2;
+const TURRET_TTL: i32 = 1000;
 
-            traces.push(ShotTrace {
-                from: shot.from.clone(),
-                to: match enemy_hit {
-                    Some((_, hit)) => *hit,
-                    _ => shot.to.clone(),
+fn get_drone_gun() -> GunConfig {
+    GunConfig {
+        reload_ticks: 30,
+        range: 8.,
+        ..GunConfig::default()
+    }
+}
+
+fn get_turret_gun() -> GunConfig {
+    GunConfig {
+        reload_ticks: 15,
+        range: 10.,
+        ..GunConfig::default()
+    }
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(VecStorage)]
+pub struct Shot {
+    from: Point,
+    to: Point,
+    force: i32,
+    owner: Option<Entity>,
+    is_hostile: bool,
+    recolor: bool,
+    // снаряд проверяется отрезком своего пути за тик, а не бесконечным лучом
+    radius: f32,
+    projectile: Option<Entity>,
+    pierce: u32,
+    ricochet: u32,
+    ignore: Option<Entity>,
+    blast_radius: f32,
+    status: Option<(StatusKind, i32)>,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct DeadByTtl {
+    ttl: i32,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct ShotTrace {
+    pub from: Point,
+    pub to: Point,
+    pub is_hostile: bool,
+    pub is_white: bool,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Color {
+    pub is_white: bool,
+    pub damage: i32,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Spawner {
+    pub radius: f32,
+    pub spawn_interval: i32,
+    pub tick_to_spawn: i32,
+    pub capacity: u32,
+    pub archetypes: Vec<(EnemyArchetype, u32)>,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct SpawnedBy {
+    pub spawner: Entity,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Health {
+    pub hp: i32,
+    pub max_hp: i32,
+}
+
+// цвет игрока: враги и пули того же цвета не убивают, а поглощаются
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Polarity {
+    pub is_white: bool,
+    pub tick_to_switch: i32,
+}
+
+impl Polarity {
+    pub fn get_sign(&self) -> i32 {
+        match self.is_white {
+            true => 1,
+            false => -1,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Energy {
+    pub value: i32,
+    pub max: i32,
+}
+
+impl Energy {
+    fn charge(&mut self, amount: i32) {
+        self.value = (self.value + amount).min(self.max);
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.value >= self.max
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum StatusKind {
+    Slow,
+    Freeze,
+    Stun,
+    Burn,
+    Haste,
+    Shield,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct StatusEffect {
+    pub kind: StatusKind,
+    pub ticks: i32,
+    pub stacks: u32,
+}
+
+// наложенные эффекты тикают, как DeadByTtl, и меняют движение и стрельбу
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct StatusEffects {
+    pub active: Vec<StatusEffect>,
+}
+
+impl StatusEffects {
+    // замедление и горение копятся до предела, остальное только продлевается
+    pub fn apply(&mut self, kind: StatusKind, ticks: i32) {
+        let max_stacks = match kind {
+            StatusKind::Slow | StatusKind::Burn => STATUS_MAX_STACKS,
+            _ => 1,
+        };
+        match self.active.iter_mut().find(|e| e.kind == kind) {
+            Some(effect) => {
+                effect.ticks = effect.ticks.max(ticks);
+                effect.stacks = (effect.stacks + 1).min(max_stacks);
+            }
+            None => self.active.push(StatusEffect {
+                kind,
+                ticks,
+                stacks: 1,
+            }),
+        }
+    }
+
+    pub fn has(&self, kind: StatusKind) -> bool {
+        self.get_stacks(kind) > 0
+    }
+
+    fn get_stacks(&self, kind: StatusKind) -> u32 {
+        self.active
+            .iter()
+            .find(|e| e.kind == kind)
+            .map_or(0, |e| e.stacks)
+    }
+
+    // замороженный и оглушённый не двигаются и не стреляют
+    pub fn can_act(&self) -> bool {
+        !self.has(StatusKind::Freeze) && !self.has(StatusKind::Stun)
+    }
+
+    pub fn get_speed_multiplier(&self) -> f32 {
+        if !self.can_act() {
+            return 0.;
+        }
+
+        let slow = 1. - SLOW_PER_STACK * self.get_stacks(StatusKind::Slow) as f32;
+        let haste = match self.has(StatusKind::Haste) {
+            true => HASTE_MULTIPLIER,
+            false => 1.,
+        };
+        slow.max(0.) * haste
+    }
+}
+
+const STATUS_MAX_STACKS: u32 = 3;
+const SLOW_PER_STACK: f32 = 0.25;
+const HASTE_MULTIPLIER: f32 = 1.5;
+const BURN_INTERVAL: i32 = 25;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum PickupKind {
+    RapidFire,
+    Speed,
+    Shield,
+    Multishot,
+}
+
+const PICKUP_KINDS: [PickupKind; 4] = [
+    PickupKind::RapidFire,
+    PickupKind::Speed,
+    PickupKind::Shield,
+    PickupKind::Multishot,
+];
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Pickup {
+    pub kind: PickupKind,
+    pub radius: f32,
+}
+
+// с какой вероятностью и что выпадает из врага, пустой список - что угодно
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Loot {
+    chance: f32,
+    kinds: Vec<PickupKind>,
+}
+
+// подобранные усиления и сколько тиков им осталось
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct PowerUps {
+    pub active: Vec<(PickupKind, i32)>,
+}
+
+impl PowerUps {
+    pub fn has(&self, kind: PickupKind) -> bool {
+        self.active.iter().any(|(k, _)| *k == kind)
+    }
+
+    // повторно подобранное усиление не складывается, а продлевается
+    fn grant(&mut self, kind: PickupKind, ticks: i32) {
+        match self.active.iter_mut().find(|(k, _)| *k == kind) {
+            Some(effect) => effect.1 = effect.1.max(ticks),
+            None => self.active.push((kind, ticks)),
+        }
+    }
+
+    fn boost(&self, config: &GunConfig) -> GunConfig {
+        let mut config = *config;
+        if self.has(PickupKind::RapidFire) {
+            config.reload_ticks /= 2;
+        }
+        if self.has(PickupKind::Multishot) {
+            config.pellets += 2;
+            config.spread = config.spread.max(MULTISHOT_SPREAD);
+        }
+        config
+    }
+}
+
+const PICKUP_RADIUS: f32 = 0.4;
+const PICKUP_TTL: i32 = 400;
+const POWER_UP_TICKS: i32 = 500;
+const MULTISHOT_SPREAD: f32 = 0.3;
+
+// рывок: короткий разгон в сторону движения, во время которого игрок неуязвим
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Dash {
+    direction: Vector,
+    pub tick_to_end: i32,
+    pub tick_to_vulnerable: i32,
+    pub tick_to_ready: i32,
+}
+
+impl Default for Dash {
+    fn default() -> Self {
+        Dash {
+            direction: Vector::zeros(),
+            tick_to_end: 0,
+            tick_to_vulnerable: 0,
+            tick_to_ready: 0,
+        }
+    }
+}
+
+impl Dash {
+    pub fn is_dashing(&self) -> bool {
+        self.tick_to_end > 0
+    }
+
+    pub fn is_invulnerable(&self) -> bool {
+        self.tick_to_vulnerable > 0
+    }
+
+    pub fn get_cooldown(&self) -> f32 {
+        self.tick_to_ready as f32 / DASH_COOLDOWN_TICKS as f32
+    }
+}
+
+const DASH_TICKS: i32 = 8;
+const DASH_SPEED_MULTIPLIER: f32 = 3.5;
+const DASH_INVULNERABLE_TICKS: i32 = 12;
+const DASH_COOLDOWN_TICKS: i32 = 60;
+
+// полная энергия выжигает всех врагов своего цвета вокруг игрока
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct Ultimate {
+    pub tick_to_ready: i32,
+}
+
+#[derive(Component, Clone, Copy, Debug, Deserialize)]
+#[storage(VecStorage)]
+#[serde(default)]
+pub struct Steering {
+    pub seek: f32,
+    pub separation: f32,
+    pub alignment: f32,
+    pub avoidance: f32,
+    pub neighbor_radius: f32,
+    pub cohesion: f32,
+    pub flock_radius: f32,
+}
+
+const SWARM_COHESION: f32 = 1.;
+
+impl Default for Steering {
+    fn default() -> Self {
+        Steering {
+            seek: 1.,
+            separation: 1.5,
+            alignment: 0.3,
+            avoidance: 2.,
+            neighbor_radius: 1.,
+            cohesion: 0.,
+            flock_radius: 4.,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct AiConfig {
+    pub sight_radius: f32,
+    pub flank_distance: Option<f32>,
+    pub charge: Option<ChargeConfig>,
+    pub retreat_health: Option<f32>,
+    pub retreat_ticks: i32,
+    pub flee_matching_color: bool,
+    pub wander_ticks: i32,
+}
+
+impl Default for AiConfig {
+    fn default() -> Self {
+        AiConfig {
+            sight_radius: 15.,
+            flank_distance: None,
+            charge: None,
+            retreat_health: None,
+            retreat_ticks: 100,
+            flee_matching_color: false,
+            wander_ticks: 75,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ChargeConfig {
+    pub range: f32,
+    pub windup_ticks: i32,
+    pub dash_ticks: i32,
+    pub speed_multiplier: f32,
+    pub cooldown_ticks: i32,
+}
+
+impl Default for ChargeConfig {
+    fn default() -> Self {
+        ChargeConfig {
+            range: 6.,
+            windup_ticks: 30,
+            dash_ticks: 25,
+            speed_multiplier: 3.,
+            cooldown_ticks: 150,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AiState {
+    Wander,
+    Chase,
+    Flank,
+    ChargeWindup,
+    Charge,
+    Retreat,
+    Flee,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Ai {
+    pub config: AiConfig,
+    pub state: AiState,
+    ticks_in_state: i32,
+    tick_to_charge: i32,
+    has_retreated: bool,
+    target: Option<Point>,
+    speed_multiplier: f32,
+}
+
+impl Ai {
+    fn new(config: AiConfig) -> Ai {
+        Ai {
+            config,
+            state: AiState::Wander,
+            ticks_in_state: 0,
+            tick_to_charge: 0,
+            has_retreated: false,
+            target: None,
+            speed_multiplier: 1.,
+        }
+    }
+
+    fn set_state(&mut self, state: AiState) {
+        if self.state != state {
+            self.state = state;
+            self.ticks_in_state = 0;
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Behavior {
+    pub tree: BehaviorTree,
+    target: Option<Point>,
+    speed_multiplier: f32,
+}
+
+impl Behavior {
+    fn new(root: behavior::Node) -> Behavior {
+        Behavior {
+            tree: BehaviorTree::new(root),
+            target: None,
+            speed_multiplier: 1.,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Boss {
+    pub name: String,
+    pub phases: Vec<BossPhase>,
+    pub phase: usize,
+    base_speed: f32,
+    tick_to_minions: i32,
+    tick_to_attack: i32,
+    charge_ticks: i32,
+}
+
+impl Boss {
+    fn new(def: BossDef, base_speed: f32) -> Boss {
+        let phases = match def.phases.len() {
+            0 => vec![BossPhase::default()],
+            _ => def.phases,
+        };
+
+        let mut boss = Boss {
+            name: def.name,
+            phases,
+            phase: 0,
+            base_speed,
+            tick_to_minions: 0,
+            tick_to_attack: 0,
+            charge_ticks: 0,
+        };
+        boss.reset_timers();
+        boss
+    }
+
+    fn reset_timers(&mut self) {
+        let phase = &self.phases[self.phase];
+        self.tick_to_minions = 0;
+        self.tick_to_attack = match phase.attack {
+            Some(BossAttack::Charge { interval, .. }) => interval,
+            None => 0,
+        };
+        self.charge_ticks = 0;
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct SpawnMarker {
+    pub tick_to_activate: i32,
+    pub telegraph_ticks: i32,
+    pub radius: f32,
+    pub is_white: bool,
+    pub archetype: EnemyArchetype,
+    flock: Option<Flock>,
+    formation: Option<FormationMember>,
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(VecStorage)]
+pub struct Flock {
+    pub group: u32,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq)]
+pub enum FormationShape {
+    V,
+    Circle,
+    Line,
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct FormationDef {
+    pub shape: FormationShape,
+    #[serde(default = "default_formation_spacing")]
+    pub spacing: f32,
+    #[serde(default = "default_formation_break_distance")]
+    pub break_distance: f32,
+}
+
+fn default_formation_spacing() -> f32 {
+    1.5
+}
+
+fn default_formation_break_distance() -> f32 {
+    6.
+}
+
+#[derive(Component, Clone, Copy, Debug)]
+#[storage(VecStorage)]
+pub struct FormationMember {
+    pub group: u32,
+    pub slot: usize,
+    pub count: usize,
+    pub def: FormationDef,
+}
+
+impl FormationMember {
+    // смещение слота в системе координат строя, где ось x смотрит вперёд
+    fn get_offset(&self, slot: usize) -> Vector {
+        let spacing = self.def.spacing;
+        match self.def.shape {
+            FormationShape::V => {
+                let row = slot.div_ceil(2) as f32;
+                let side = if slot % 2 == 1 { -1. } else { 1. };
+                Vector::new(-row * spacing, side * row * spacing)
+            }
+            FormationShape::Line => {
+                let center = (self.count as f32 - 1.) / 2.;
+                Vector::new(0., (slot as f32 - center) * spacing)
+            }
+            FormationShape::Circle => {
+                let radius =
+                    (spacing * self.count as f32 / (2. * std::f32::consts::PI)).max(spacing);
+                let angle = slot as f32 * 2. * std::f32::consts::PI / self.count.max(1) as f32;
+                Vector::new(angle.cos(), angle.sin()) * radius
+            }
+        }
+    }
+}
+
+#[derive(Component, Debug, Default)]
+#[storage(NullStorage)]
+pub struct EnemyKillEvent;
+
+// взрыв наносит урон один раз, а дальше только виден, пока не истечёт ttl
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Explosion {
+    pub radius: f32,
+    force: i32,
+    is_hostile: bool,
+    pub is_reaction: bool,
+    is_resolved: bool,
+    has_reacted: bool,
+}
+
+impl Explosion {
+    pub fn new(radius: f32, force: i32, is_hostile: bool) -> Explosion {
+        Explosion {
+            radius,
+            force,
+            is_hostile,
+            is_reaction: false,
+            is_resolved: false,
+            has_reacted: false,
+        }
+    }
+
+    pub fn is_white(&self) -> bool {
+        self.force > 0
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ExplosiveConfig {
+    pub radius: f32,
+    pub damage: i32,
+}
+
+impl Default for ExplosiveConfig {
+    fn default() -> Self {
+        ExplosiveConfig {
+            radius: 2.5,
+            damage: 1,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Explosive {
+    pub config: ExplosiveConfig,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Bomb {
+    target: Point,
+    fuse: i32,
+    force: i32,
+}
+
+#[derive(Component, Debug, Default)]
+#[storage(VecStorage)]
+pub struct BombThrower {
+    tick_to_throw: i32,
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Scope {
+    pub scope: u32,
+}
+
+impl Enemy {
+    fn new(archetype: &EnemyArchetype) -> Enemy {
+        Enemy {
+            radius: archetype.radius,
+            max_speed: archetype.max_speed,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelStatus {
+    Running,
+    Won,
+    Lost,
+}
+
+pub struct WaveDirector {
+    pub level: Option<Level>,
+    pub status: LevelStatus,
+    pub tick: u32,
+    pub next_wave: usize,
+    last_wave_tick: u32,
+}
+
+impl Default for WaveDirector {
+    fn default() -> Self {
+        WaveDirector {
+            level: None,
+            status: LevelStatus::Running,
+            tick: 0,
+            next_wave: 0,
+            last_wave_tick: 0,
+        }
+    }
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Position {
+    pub point: Point2<f32>,
+}
+
+// форма задаётся относительно позиции препятствия
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type")]
+pub enum Shape {
+    Circle { radius: f32 },
+    Box { width: f32, height: f32 },
+    Polygon { points: Vec<(f32, f32)> },
+}
+
+// препятствие не пропускает игрока, врагов и выстрелы
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Obstacle {
+    pub shape: Shape,
+}
+
+impl Obstacle {
+    pub fn get_vertices(&self, center: &Point) -> Vec<Point> {
+        match &self.shape {
+            Shape::Circle { .. } => vec![],
+            Shape::Box { width, height } => {
+                let (x, y) = (width / 2., height / 2.);
+                vec![
+                    center + Vector::new(-x, -y),
+                    center + Vector::new(x, -y),
+                    center + Vector::new(x, y),
+                    center + Vector::new(-x, y),
+                ]
+            }
+            Shape::Polygon { points } => points
+                .iter()
+                .map(|(x, y)| center + Vector::new(*x, *y))
+                .collect(),
+        }
+    }
+
+    fn get_bounding_radius(&self) -> f32 {
+        match &self.shape {
+            Shape::Circle { radius } => *radius,
+            Shape::Box { width, height } => Vector::new(*width, *height).norm() / 2.,
+            Shape::Polygon { points } => points
+                .iter()
+                .map(|(x, y)| Vector::new(*x, *y).norm())
+                .fold(0., f32::max),
+        }
+    }
+
+    fn get_push_out(&self, center: &Point, point: &Point, radius: f32) -> Option<Vector> {
+        match &self.shape {
+            Shape::Circle {
+                radius: obstacle_radius,
+            } => {
+                let offset = point - center;
+                let min_distance = radius + obstacle_radius;
+                match offset.norm() < min_distance {
+                    true => Some(
+                        offset
+                            .try_normalize(0.001)
+                            .unwrap_or_else(|| Vector::new(1., 0.))
+                            * (min_distance - offset.norm()),
+                    ),
+                    false => None,
+                }
+            }
+            _ => math::get_polygon_push_out(point, radius, &self.get_vertices(center)),
+        }
+    }
+
+    fn get_cross_point(&self, center: &Point, from: &Point, to: &Point) -> Option<Point> {
+        match &self.shape {
+            Shape::Circle { radius } => {
+                if has_circles_collision(from, center, *radius) {
+                    return Some(*from);
+                }
+                let length = (to - from).norm();
+                math::get_cross_points_with_sphere(center, *radius, from, to)
+                    .into_iter()
+                    .filter(|p| (p - from).norm() <= length)
+                    .min_by(|a, b| compare_vector_lengths(&(a - from), &(b - from)))
+            }
+            Shape::Box { width, height } => {
+                let half = Vector::new(width / 2., height / 2.);
+                math::get_segment_box_cross_point(from, to, &(center - half), &(center + half))
+            }
+            Shape::Polygon { .. } => {
+                math::get_segment_polygon_cross_point(from, to, &self.get_vertices(center))
+            }
+        }
+    }
+}
+
+// ближайшая к началу отрезка точка, где он упирается в препятствие
+fn get_obstacle_hit(from: &Point, to: &Point, obstacles: &[(Point, &Obstacle)]) -> Option<Point> {
+    obstacles
+        .iter()
+        .filter_map(|(center, obstacle)| obstacle.get_cross_point(center, from, to))
+        .min_by(|a, b| compare_vector_lengths(&(a - from), &(b - from)))
+}
+
+#[derive(Component, Debug)]
+#[storage(VecStorage)]
+pub struct Velocity {
+    velocity: Vector,
+}
+
+impl Default for MainState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MainState {
+    pub fn new() -> MainState {
+        let mut spec_world = specs::World::new();
+        spec_world.add_resource(Settings {
+            boundary: Boundary::new(ArenaShape::Rectangle, Point::new(50., 40.), None),
+            fps: 50,
+            gun_reload_ticks: 5,
+            player_health: 5,
+            spawn_safe_distance: 8.,
+            spawn_telegraph_ticks: 50,
+            movement: MovementConfig::default(),
+            rnd: SmallRng::seed_from_u64(1),
+        });
+
+        let mut dispatcher = DispatcherBuilder::new()
+            .with(RemoveByTtlSystem, "", &[])
+            .with(UpdateTtlSystem, "", &[])
+            .with(PolaritySystem, "polarity", &[])
+            .with(PlayerPositionSystem, "player_position", &[])
+            .with(PlayerVelocitySystem, "", &[])
+            .with(ArenaSystem, "arena", &[])
+            .with(StatusSystem, "", &[])
+            .with(EnemyAiSystem, "", &[])
+            .with(BehaviorTreeSystem, "", &[])
+            .with(EnemyGunSystem, "", &[])
+            .with(EnemiesVelocitySystem, "", &[])
+            .with(EnemiesPositionSystem, "enemies_position", &[])
+            .with(
+                ConfineToArenaSystem,
+                "warzone",
+                &["player_position", "enemies_position", "arena"],
+            )
+            .with(PickupSystem, "", &["warzone"])
+            .with(FlowFieldSystem, "flow_field", &["warzone"])
+            .with(
+                ObstacleCollisionSystem,
+                "obstacles",
+                &["warzone", "enemies_position"],
+            )
+            .with(AllySystem, "ally", &["obstacles"])
+            .with(LockOnSystem, "lock_on", &[])
+            .with(GunShotSystem, "gun_shot", &["lock_on", "polarity", "ally"])
+            .with(ProjectileSystem, "projectile", &["gun_shot"])
+            .with(ShotSystem, "shot", &["gun_shot", "projectile"])
+            .with(BombSystem, "bomb", &["shot"])
+            .with(UltimateSystem, "ultimate", &["shot"])
+            .with(ExplosionSystem, "explosion", &["bomb", "ultimate"])
+            .with(DamagePlayerSystem, "damage_player", &["shot"])
+            .with(
+                ExplosiveSystem,
+                "explosive",
+                &["explosion", "damage_player"],
+            )
+            .with(LootSystem, "loot", &["explosive"])
+            .with(RemoveOvercoloredEmenySystem, "", &["explosive", "loot"])
+            .with(RemoveDestroyedSystem, "", &["explosive", "loot"])
+            .with(BossSystem, "", &[])
+            .with(ScopeSystem, "", &[])
+            .with(SpawnMarkerSystem, "", &[])
+            .with(SpawnEnemiesSystem, "", &[])
+            .with(WaveDirectorSystem, "", &[])
+            .with(SpawnerSystem, "", &[])
+            .with(SpawnSystem, "", &[])
+            .build();
+
+        dispatcher.setup(&mut spec_world.res);
+
+        spec_world.register::<Color>();
+        spec_world.register::<Enemy>();
+        spec_world.register::<Spawner>();
+        spec_world.register::<Health>();
+        spec_world.register::<Scope>();
+        spec_world.register::<Arsenal>();
+        spec_world.register::<Obstacle>();
+
+        MainState {
+            spec_world,
+            dispatcher,
+        }
+    }
+
+    pub fn init(self: &mut MainState) {
+        let (center, gun_reload_ticks, player_health, max_speed) = {
+            let settings = self.spec_world.read_resource::<Settings>();
+            (
+                settings.boundary.get_center(),
+                settings.gun_reload_ticks,
+                settings.player_health,
+                settings.movement.max_speed,
+            )
+        };
+
+        let (weapons, drones) = {
+            let director = self.spec_world.read_resource::<WaveDirector>();
+            let weapons = match &director.level {
+                Some(level) if !level.weapons.is_empty() => level.weapons.clone(),
+                _ => get_default_weapons(gun_reload_ticks),
+            };
+            let drones = director
+                .level
+                .as_ref()
+                .map_or_else(Vec::new, |level| level.drones.clone());
+            (weapons, drones)
+        };
+
+        self.spec_world
+            .create_entity()
+            .with(Player {
+                max_speed,
+                radius: 0.25,
+            })
+            .with(Position { point: center })
+            .with(Velocity {
+                velocity: Vector2::new(0., 0.),
+            })
+            .with(Gun::new(weapons[0].gun))
+            .with(Arsenal::new(weapons))
+            .with(Health {
+                hp: player_health,
+                max_hp: player_health,
+            })
+            .with(BombThrower::default())
+            .with(LockOn::default())
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
+            })
+            .with(Energy {
+                value: 0,
+                max: ENERGY_MAX,
+            })
+            .with(Ultimate::default())
+            .with(Dash::default())
+            .with(PowerUps::default())
+            .with(StatusEffects::default())
+            .build();
+
+        // дроны есть только там, где их заказал уровень, и расходятся по орбите поровну
+        for (i, rule) in drones.iter().enumerate() {
+            let angle = 2. * std::f32::consts::PI * i as f32 / drones.len() as f32;
+            self.spec_world
+                .create_entity()
+                .with(Ally {
+                    kind: AllyKind::Drone,
+                    rule: *rule,
+                    target: None,
+                })
+                .with(Orbit {
+                    angle,
+                    radius: DRONE_ORBIT_RADIUS,
+                    angular_speed: DRONE_ANGULAR_SPEED,
+                })
+                .with(Position { point: center })
+                .with(Gun::new(get_drone_gun()))
+                .with(Trigger::default())
+                .build();
+        }
+
+        self.spec_world
+            .create_entity()
+            .with(Scope { scope: 0 })
+            .build();
+
+        if self
+            .spec_world
+            .read_resource::<WaveDirector>()
+            .level
+            .is_none()
+        {
+            self.spec_world.write_resource::<SpawnQueue>().push(
+                SpawnPattern::Edges,
+                10,
+                EnemyArchetype::default(),
+            );
+        }
+    }
+
+    pub fn load_level(self: &mut MainState, level: Level) {
+        {
+            let mut settings = self.spec_world.write_resource::<Settings>();
+            settings.boundary = Boundary::new(
+                level.arena.shape,
+                Point::new(level.arena.width, level.arena.height),
+                level.arena.shrink,
+            );
+            settings.spawn_safe_distance = level.arena.spawn_safe_distance;
+            settings.spawn_telegraph_ticks = level.arena.spawn_telegraph_ticks;
+            settings.movement = level.movement;
+        }
+
+        for def in level.spawners.iter() {
+            let spawner = Spawner {
+                radius: def.radius,
+                spawn_interval: def.interval,
+                tick_to_spawn: def.interval,
+                capacity: def.capacity,
+                archetypes: def
+                    .archetypes
+                    .iter()
+                    .map(|a| (level.get_archetype(&a.archetype), a.weight))
+                    .collect(),
+            };
+
+            let builder = self
+                .spec_world
+                .create_entity()
+                .with(Position {
+                    point: Point::new(def.x, def.y),
+                })
+                .with(spawner);
+
+            match def.health {
+                Some(hp) => builder.with(Health { hp, max_hp: hp }).build(),
+                None => builder.build(),
+            };
+        }
+
+        for def in level.obstacles.iter() {
+            self.spec_world
+                .create_entity()
+                .with(Position {
+                    point: Point::new(def.x, def.y),
+                })
+                .with(Obstacle {
+                    shape: def.shape.clone(),
+                })
+                .build();
+        }
+
+        *self.spec_world.write_resource::<WaveDirector>() = WaveDirector {
+            level: Some(level),
+            ..WaveDirector::default()
+        };
+    }
+
+    pub fn step(self: &mut MainState) {
+        self.dispatcher.dispatch(&self.spec_world.res);
+        self.spec_world.maintain();
+    }
+
+    pub fn set_player_direction(self: &mut MainState, direction: &mut Vector) {
+        if direction.norm() > 1.0 {
+            direction.try_normalize_mut(0.01);
+        }
+
+        self.spec_world.write_resource::<Input>().player_direction = *direction;
+    }
+
+    pub fn set_shoot_point(self: &mut MainState, shoot_point: Option<Point>) {
+        self.spec_world.write_resource::<Input>().shoot_point = shoot_point;
+    }
+
+    pub fn set_shoot_force(self: &mut MainState, force: i32) {
+        self.spec_world.write_resource::<Input>().shoot_force = force.abs();
+    }
+
+    pub fn dash(self: &mut MainState) {
+        self.spec_world.write_resource::<Input>().dash = true;
+    }
+
+    pub fn use_ultimate(self: &mut MainState) {
+        self.spec_world.write_resource::<Input>().use_ultimate = true;
+    }
+
+    pub fn switch_polarity(self: &mut MainState) {
+        self.spec_world.write_resource::<Input>().switch_polarity = true;
+    }
+
+    pub fn throw_bomb(self: &mut MainState, target: Point) {
+        self.spec_world.write_resource::<Input>().bomb_target = Some(target);
+    }
+
+    pub fn place_turret(self: &mut MainState, point: Point) {
+        self.spec_world.write_resource::<Input>().turret_point = Some(point);
+    }
+
+    pub fn reload(self: &mut MainState) {
+        use specs::Join;
+
+        let player_storage = self.spec_world.read_storage::<Player>();
+        let mut gun_storage = self.spec_world.write_storage::<Gun>();
+        for (gun, _) in (&mut gun_storage, &player_storage).join() {
+            gun.reload();
+        }
+    }
+
+    pub fn select_weapon(self: &mut MainState, index: usize) {
+        use specs::Join;
+
+        let mut arsenal_storage = self.spec_world.write_storage::<Arsenal>();
+        let mut gun_storage = self.spec_world.write_storage::<Gun>();
+        for (arsenal, gun) in (&mut arsenal_storage, &mut gun_storage).join() {
+            arsenal.select(index, gun);
+        }
+    }
+
+    pub fn get_boundary(self: &MainState) -> Boundary {
+        self.spec_world.read_resource::<Settings>().boundary
+    }
+}
+
+// смена цвета по запросу игрока, не чаще раза за перезарядку
+struct PolaritySystem;
+
+impl<'a> System<'a> for PolaritySystem {
+    type SystemData = (WriteStorage<'a, Polarity>, Write<'a, Input>);
+
+    fn run(&mut self, (mut polarity_storage, mut input): Self::SystemData) {
+        use specs::Join;
+
+        let is_switching = input.switch_polarity;
+        input.switch_polarity = false;
+
+        for polarity in (&mut polarity_storage).join() {
+            if polarity.tick_to_switch > 0 {
+                polarity.tick_to_switch -= 1;
+            } else if is_switching {
+                polarity.is_white = !polarity.is_white;
+                polarity.tick_to_switch = POLARITY_SWITCH_TICKS;
+            }
+        }
+    }
+}
+
+const POLARITY_SWITCH_TICKS: i32 = 15;
+const ENERGY_MAX: i32 = 50;
+const ABSORB_ENERGY: i32 = 1;
+const KILL_ENERGY: i32 = 2;
+
+struct ScopeSystem;
+
+impl<'a> System<'a> for ScopeSystem {
+    type SystemData = (
+        WriteStorage<'a, Scope>,
+        ReadStorage<'a, EnemyKillEvent>,
+        WriteStorage<'a, Energy>,
+        specs::Entities<'a>,
+    );
+
+    fn run(
+        &mut self,
+        (mut scope_storage, kill_event_storage, mut energy_storage, entities): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let scope = (&mut scope_storage).join().next();
+        let kills = (&entities, &kill_event_storage)
+            .join()
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+
+        if let Some(s) = scope {
+            s.scope += kills.len() as u32;
+        }
+
+        for energy in (&mut energy_storage).join() {
+            energy.charge(kills.len() as i32 * KILL_ENERGY);
+        }
+
+        for e in kills {
+            entities.delete(e).unwrap();
+        }
+    }
+}
+
+struct DamagePlayerSystem;
+
+impl<'a> System<'a> for DamagePlayerSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Enemy>,
+        WriteStorage<'a, Color>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Boss>,
+        ReadStorage<'a, Polarity>,
+        WriteStorage<'a, Energy>,
+        ReadStorage<'a, Dash>,
+        ReadStorage<'a, StatusEffects>,
+        specs::Entities<'a>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            pos_storage,
+            player_storage,
+            enemy_storage,
+            mut color_storage,
+            health_storage,
+            boss_storage,
+            polarity_storage,
+            mut energy_storage,
+            dash_storage,
+            status_storage,
+            entities,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player = (&entities, &pos_storage, &player_storage).join().next();
+
+        if let Some((entity, p_pos, p)) = player {
+            let collisions = (
+                &entities,
+                &pos_storage,
+                &enemy_storage,
+                (&color_storage).maybe(),
+            )
+                .join()
+                .filter(|(_, e_pos, e, _)| {
+                    let offset = settings.boundary.get_offset(&e_pos.point, &p_pos.point);
+                    offset.norm() < p.radius + e.radius
+                })
+                // уже перекрашенный враг погибает в этом тике и больше не касается
+                .filter(|(_, _, _, color)| {
+                    !color.is_some_and(|c| is_overcolored(c.is_white, c.damage))
+                })
+                .map(|(enemy, _, _, color)| (enemy, color.map(|c| c.is_white)))
+                .collect::<Vec<_>>();
+
+            // враг своего цвета поглощается, чужого - убивает
+            let is_white = polarity_storage.get(entity).map(|p| p.is_white);
+            for (enemy, enemy_is_white) in collisions {
+                if is_white.is_some() && enemy_is_white == is_white {
+                    // босса и врагов со здоровьем касание не снимает, только выстрелы
+                    if boss_storage.contains(enemy) || health_storage.contains(enemy) {
+                        continue;
+                    }
+                    // поглощение перекрашивает врага, и он погибает обычным путём
+                    // с событием убийства, добычей и взрывом
+                    if let Some(color) = color_storage.get_mut(enemy) {
+                        color.damage = match color.is_white {
+                            true => 1,
+                            false => -1,
+                        };
+                    }
+                    if let Some(energy) = energy_storage.get_mut(entity) {
+                        energy.charge(ABSORB_ENERGY);
+                    }
+                    continue;
+                }
+
+                if is_protected(dash_storage.get(entity), status_storage.get(entity)) {
+                    continue;
+                }
+
+                entities.delete(entity).unwrap();
+                break;
+            }
+        }
+    }
+}
+
+struct SpawnEnemiesSystem;
+
+impl<'a> System<'a> for SpawnEnemiesSystem {
+    type SystemData = (
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, SpawnMarker>,
+        ReadStorage<'a, Scope>,
+        Write<'a, SpawnQueue>,
+        Read<'a, WaveDirector>,
+    );
+
+    fn run(
+        &mut self,
+        (enemy_storage, marker_storage, scope_storage, mut spawn_queue, director): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        if director.level.is_some() {
+            return;
+        }
+
+        let maybe_scope = (&scope_storage).join().next();
+        if let Some(scope) = maybe_scope {
+            let target_count = scope.scope / 5 + 10;
+            let current_count = (enemy_storage.count() + marker_storage.count()) as u32
+                + spawn_queue.pending_count();
+            if current_count < target_count {
+                spawn_queue.push(SpawnPattern::Edges, 1, EnemyArchetype::default());
+            }
+        }
+    }
+}
+
+struct SpawnSystem;
+
+impl<'a> System<'a> for SpawnSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, SpawnMarker>,
+        WriteStorage<'a, SpawnedBy>,
+        ReadStorage<'a, Player>,
+        Write<'a, SpawnQueue>,
+        Write<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut pos_storage,
+            mut marker_storage,
+            mut spawned_by_storage,
+            player_storage,
+            mut spawn_queue,
+            mut settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player_pos = (&pos_storage, &player_storage)
+            .join()
+            .next()
+            .map(|(pos, _)| pos.point);
+
+        let settings = &mut *settings;
+        let spawn_queue = &mut *spawn_queue;
+        for request in spawn_queue.requests.drain(..) {
+            let group = match request.archetype.swarm || request.formation.is_some() {
+                true => {
+                    spawn_queue.next_group += 1;
+                    Some(spawn_queue.next_group)
+                }
+                false => None,
+            };
+
+            let points = get_spawn_points(
+                request.pattern,
+                request.count,
+                request.archetype.radius,
+                &settings.boundary,
+                player_pos,
+                settings.spawn_safe_distance,
+                &mut settings.rnd,
+            );
+
+            let count = points.len();
+            for (slot, point) in points.into_iter().enumerate() {
+                let flock = match (group, request.archetype.swarm) {
+                    (Some(group), true) => Some(Flock { group }),
+                    _ => None,
+                };
+                let formation = match (group, request.formation) {
+                    (Some(group), Some(def)) => Some(FormationMember {
+                        group,
+                        slot,
+                        count,
+                        def,
+                    }),
+                    _ => None,
+                };
+
+                let marker = entities
+                    .build_entity()
+                    .with(Position { point }, &mut pos_storage)
+                    .with(
+                        SpawnMarker {
+                            tick_to_activate: settings.spawn_telegraph_ticks,
+                            telegraph_ticks: settings.spawn_telegraph_ticks,
+                            radius: request.archetype.radius,
+                            is_white: request.archetype.get_is_white(&mut settings.rnd),
+                            archetype: request.archetype.clone(),
+                            flock,
+                            formation,
+                        },
+                        &mut marker_storage,
+                    )
+                    .build();
+
+                if let Some(spawner) = request.spawner {
+                    spawned_by_storage
+                        .insert(marker, SpawnedBy { spawner })
+                        .unwrap();
+                }
+            }
+        }
+    }
+}
+
+struct SpawnMarkerSystem;
+
+impl<'a> System<'a> for SpawnMarkerSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, SpawnMarker>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Enemy>,
+        WriteStorage<'a, Velocity>,
+        WriteStorage<'a, Color>,
+        WriteStorage<'a, SpawnedBy>,
+        ReadStorage<'a, Player>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut marker_storage,
+            mut pos_storage,
+            mut enemy_storage,
+            mut vel_storage,
+            mut color_storage,
+            mut spawned_by_storage,
+            player_storage,
+            lazy,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player = (&pos_storage, &player_storage)
+            .join()
+            .next()
+            .map(|(pos, p)| (pos.point, p.radius));
+
+        let mut activated = Vec::new();
+        for (entity, marker, pos) in (&entities, &mut marker_storage, &pos_storage).join() {
+            if marker.tick_to_activate > 0 {
+                marker.tick_to_activate -= 1;
+                continue;
+            }
+
+            // не активируем врага прямо на игроке, ждём пока он отойдёт
+            let blocked = match player {
+                Some((p_pos, p_radius)) => {
+                    has_circles_collision(&p_pos, &pos.point, p_radius + marker.radius)
+                }
+                None => false,
+            };
+
+            if !blocked {
+                activated.push((
+                    entity,
+                    pos.point,
+                    marker.is_white,
+                    marker.archetype.clone(),
+                    marker.flock,
+                    marker.formation,
+                ));
+            }
+        }
+
+        for (marker, point, is_white, archetype, flock, formation) in activated {
+            let spawned_by = spawned_by_storage.remove(marker);
+            entities.delete(marker).unwrap();
+
+            let enemy = entities
+                .build_entity()
+                .with(Enemy::new(&archetype), &mut enemy_storage)
+                .with(Position { point }, &mut pos_storage)
+                .with(
+                    Velocity {
+                        velocity: Vector::zeros(),
+                    },
+                    &mut vel_storage,
+                )
+                .with(
+                    Color {
+                        is_white,
+                        damage: 0,
+                    },
+                    &mut color_storage,
+                )
+                .build();
+            if let Some(spawned_by) = spawned_by {
+                spawned_by_storage.insert(enemy, spawned_by).unwrap();
+            }
+
+            // без стягивания стая не держится вместе, поэтому по умолчанию у неё свой вес
+            let steering = match flock {
+                Some(_) if archetype.steering.cohesion == 0. => Steering {
+                    cohesion: SWARM_COHESION,
+                    ..archetype.steering
+                },
+                _ => archetype.steering,
+            };
+            lazy.insert(enemy, steering);
+
+            if let Some(flock) = flock {
+                lazy.insert(enemy, flock);
+            }
+
+            if let Some(formation) = formation {
+                lazy.insert(enemy, formation);
+            }
+
+            if let Some(ai) = archetype.ai {
+                lazy.insert(enemy, Ai::new(ai));
+            }
+
+            if let Some(root) = archetype.behavior {
+                lazy.insert(enemy, Behavior::new(root));
+            }
+
+            if let Some(gun) = archetype.gun {
+                lazy.insert(enemy, Gun::new(gun));
+                lazy.insert(enemy, Trigger::default());
+            }
+
+            if let Some(hp) = archetype.health {
+                lazy.insert(enemy, Health { hp, max_hp: hp });
+            }
+
+            if let Some(config) = archetype.explosive {
+                lazy.insert(enemy, Explosive { config });
+            }
+
+            if archetype.drop_chance > 0. {
+                lazy.insert(
+                    enemy,
+                    Loot {
+                        chance: archetype.drop_chance,
+                        kinds: archetype.drops.clone(),
+                    },
+                );
+            }
+
+            if let Some(boss) = archetype.boss {
+                lazy.insert(enemy, Boss::new(boss, archetype.max_speed));
+            }
+        }
+    }
+}
+
+struct SpawnerSystem;
+
+impl<'a> System<'a> for SpawnerSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Spawner>,
+        ReadStorage<'a, SpawnedBy>,
+        ReadStorage<'a, Position>,
+        Write<'a, SpawnQueue>,
+        Write<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut spawner_storage,
+            spawned_by_storage,
+            pos_storage,
+            mut spawn_queue,
+            mut settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        for (entity, spawner, pos) in (&entities, &mut spawner_storage, &pos_storage).join() {
+            if spawner.tick_to_spawn > 0 {
+                spawner.tick_to_spawn -= 1;
+                continue;
+            }
+
+            let alive_count = (&spawned_by_storage)
+                .join()
+                .filter(|s| s.spawner == entity)
+                .count() as u32;
+            if alive_count >= spawner.capacity {
+                continue;
+            }
+
+            let archetype = choose_archetype(&spawner.archetypes, &mut settings.rnd);
+            spawn_queue.push_from_spawner(entity, pos.point, archetype);
+            spawner.tick_to_spawn = spawner.spawn_interval;
+        }
+    }
+}
+
+fn choose_archetype<R: Rng>(archetypes: &[(EnemyArchetype, u32)], rnd: &mut R) -> EnemyArchetype {
+    let total = archetypes.iter().map(|(_, weight)| weight).sum::<u32>();
+    if total == 0 {
+        return EnemyArchetype::default();
+    }
+
+    let mut roll = rnd.gen::<u32>() % total;
+    for (archetype, weight) in archetypes {
+        if roll < *weight {
+            return archetype.clone();
+        }
+        roll -= weight;
+    }
+
+    EnemyArchetype::default()
+}
+
+struct WaveDirectorSystem;
+
+impl<'a> System<'a> for WaveDirectorSystem {
+    type SystemData = (
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, SpawnMarker>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Scope>,
+        ReadStorage<'a, Spawner>,
+        Write<'a, SpawnQueue>,
+        Write<'a, WaveDirector>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            enemy_storage,
+            marker_storage,
+            player_storage,
+            scope_storage,
+            spawner_storage,
+            mut spawn_queue,
+            mut director,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let director = &mut *director;
+        let level = match &director.level {
+            Some(level) => level,
+            None => return,
+        };
+
+        if director.status != LevelStatus::Running {
+            return;
+        }
+
+        if player_storage.count() == 0 {
+            director.status = LevelStatus::Lost;
+            return;
+        }
+
+        let scope = (&scope_storage).join().next().map_or(0, |s| s.scope);
+        let on_field_count = (enemy_storage.count() + marker_storage.count()) as u32;
+
+        if let Some(wave) = level.waves.get(director.next_wave) {
+            let is_triggered = match wave.trigger {
+                WaveTrigger::Tick { tick } => director.tick >= tick,
+                WaveTrigger::Delay { ticks } => director.tick - director.last_wave_tick >= ticks,
+                WaveTrigger::Scope { scope: s } => scope >= s,
+                WaveTrigger::Cleared => on_field_count + spawn_queue.pending_count() == 0,
+            };
+
+            if is_triggered {
+                for group in wave.groups.iter() {
+                    let archetype = level.get_archetype(&group.archetype);
+                    match group.formation {
+                        Some(formation) => spawn_queue.push_in_formation(
+                            group.pattern,
+                            group.count,
+                            archetype,
+                            formation,
+                        ),
+                        None => spawn_queue.push(group.pattern, group.count, archetype),
+                    }
+                }
+
+                director.next_wave += 1;
+                director.last_wave_tick = director.tick;
+            }
+        }
+
+        let is_won = match level.win {
+            WinCondition::AllWavesCleared => {
+                director.next_wave >= level.waves.len()
+                    && on_field_count + spawn_queue.pending_count() == 0
+            }
+            WinCondition::Scope { scope: s } => scope >= s,
+            WinCondition::Survive { ticks } => director.tick >= ticks,
+            WinCondition::SpawnersDestroyed => spawner_storage.count() == 0,
+        };
+
+        if is_won {
+            director.status = LevelStatus::Won;
+        }
+
+        director.tick += 1;
+    }
+}
+
+fn get_spawn_points<R: Rng>(
+    pattern: SpawnPattern,
+    count: u32,
+    radius: f32,
+    boundary: &Boundary,
+    player_pos: Option<Point>,
+    safe_distance: f32,
+    rnd: &mut R,
+) -> Vec<Point> {
+    const ATTEMPTS: u32 = 10;
+
+    let is_safe = |p: &Point| match player_pos {
+        Some(player) => boundary.get_offset(p, &player).norm() >= safe_distance,
+        None => true,
+    };
+
+    let mut points = Vec::new();
+    for _ in 0..ATTEMPTS {
+        points = get_pattern_points(
+            pattern,
+            count,
+            radius,
+            boundary,
+            player_pos,
+            safe_distance,
+            rnd,
+        );
+        if points.iter().all(&is_safe) {
+            return points;
+        }
+    }
+
+    points.into_iter().filter(is_safe).collect()
+}
+
+fn get_pattern_points<R: Rng>(
+    pattern: SpawnPattern,
+    count: u32,
+    radius: f32,
+    boundary: &Boundary,
+    player_pos: Option<Point>,
+    safe_distance: f32,
+    rnd: &mut R,
+) -> Vec<Point> {
+    let spacing = radius * 2.2;
+    let center = player_pos.unwrap_or_else(|| boundary.get_center());
+
+    let points = match pattern {
+        SpawnPattern::Edges => (0..count)
+            .map(|_| boundary.get_random_edge_point(rnd).0)
+            .collect::<Vec<_>>(),
+        SpawnPattern::Corners => {
+            let corners = boundary.get_corners();
+            let first = rnd.gen::<usize>() % corners.len();
+
+            (0..count as usize)
+                .map(|i| {
+                    let (corner, inward) = corners[(first + i) % corners.len()];
+                    let depth = (i / corners.len()) as f32 * spacing;
+                    corner + inward.normalize() * (radius + depth)
+                })
+                .collect()
+        }
+        SpawnPattern::Ring {
+            radius: ring_radius,
+        } => {
+            let ring_radius = ring_radius.max(safe_distance + radius);
+            let offset = rnd.gen::<f32>() * std::f32::consts::PI * 2.;
+
+            (0..count)
+                .map(|i| {
+                    let angle = offset + i as f32 * std::f32::consts::PI * 2. / count as f32;
+                    center + Vector::new(angle.cos(), angle.sin()) * ring_radius
+                })
+                .collect()
+        }
+        SpawnPattern::Line { length } => {
+            let (start, along) = boundary.get_random_edge_point(rnd);
+            let step = match count {
+                0 | 1 => 0.,
+                _ => (length / (count - 1) as f32).max(spacing),
+            };
+            let first = start - along * step * (count.max(1) - 1) as f32 / 2.;
+
+            (0..count)
+                .map(|i| first + along * step * i as f32)
+                .collect()
+        }
+        SpawnPattern::Cluster => {
+            let (start, _) = boundary.get_random_edge_point(rnd);
+            get_cluster_points(&start, count, spacing)
+        }
+        SpawnPattern::At { x, y } => get_cluster_points(&Point::new(x, y), count, spacing),
+    };
+
+    points
+        .into_iter()
+        .map(|p| boundary.confine(&p, radius))
+        .collect()
+}
+
+fn get_cluster_points(center: &Point, count: u32, spacing: f32) -> Vec<Point> {
+    let golden_angle = std::f32::consts::PI * (3. - 5f32.sqrt());
+
+    (0..count)
+        .map(|i| {
+            let angle = i as f32 * golden_angle;
+            let distance = spacing * (i as f32).sqrt();
+            center + Vector::new(angle.cos(), angle.sin()) * distance
+        })
+        .collect()
+}
+
+struct UpdateTtlSystem;
+
+impl<'a> System<'a> for UpdateTtlSystem {
+    type SystemData = WriteStorage<'a, DeadByTtl>;
+
+    fn run(&mut self, mut ttl_storage: Self::SystemData) {
+        use specs::Join;
+
+        (&mut ttl_storage).join().for_each(|d| d.ttl -= 1);
+    }
+}
+
+struct RemoveByTtlSystem;
+
+impl<'a> System<'a> for RemoveByTtlSystem {
+    type SystemData = (WriteStorage<'a, DeadByTtl>, specs::Entities<'a>);
+
+    fn run(&mut self, (ttl_storage, entities): Self::SystemData) {
+        use specs::Join;
+
+        (&entities, &ttl_storage)
+            .join()
+            .filter(|(_, d)| d.ttl <= 0)
+            .for_each(|(e, _)| {
+                entities.delete(e).unwrap();
+            });
+    }
+}
+
+struct ConfineToArenaSystem;
+
+impl<'a> System<'a> for ConfineToArenaSystem {
+    type SystemData = (
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Enemy>,
+        specs::Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (mut pos_storage, player_storage, enemy_storage, settings): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        // сжимающаяся арена выталкивает внутрь и врагов, а на торе они тоже переходят через край
+        for (pos, player, enemy) in (
+            &mut pos_storage,
+            player_storage.maybe(),
+            enemy_storage.maybe(),
+        )
+            .join()
+        {
+            let radius = match (player, enemy) {
+                (Some(player), _) => player.radius,
+                (None, Some(enemy)) => enemy.radius,
+                (None, None) => continue,
+            };
+            pos.point = settings.boundary.confine(&pos.point, radius);
+        }
+    }
+}
+
+struct ArenaSystem;
+
+impl<'a> System<'a> for ArenaSystem {
+    type SystemData = Write<'a, Settings>;
+
+    fn run(&mut self, mut settings: Self::SystemData) {
+        let fps = settings.fps;
+        settings.boundary.update(fps);
+    }
+}
+
+struct PlayerVelocitySystem;
+impl<'a> System<'a> for PlayerVelocitySystem {
+    type SystemData = (
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Dash>,
+        ReadStorage<'a, StatusEffects>,
+        specs::Write<'a, Input>,
+        specs::Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut vel_storage,
+            player_storage,
+            mut dash_storage,
+            status_storage,
+            mut input,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let is_dash_requested = input.dash;
+        input.dash = false;
+
+        for (vel, player, dash, statuses) in (
+            &mut vel_storage,
+            &player_storage,
+            (&mut dash_storage).maybe(),
+            status_storage.maybe(),
+        )
+            .join()
+        {
+            let max_speed = player.max_speed * statuses.map_or(1., |s| s.get_speed_multiplier());
+            let can_act = statuses.is_none_or(|s| s.can_act());
+            let target =
+                get_player_target_velocity(&input.player_direction, max_speed, &settings.movement)
+                    / settings.fps as f32;
+
+            if let Some(dash) = dash {
+                dash.tick_to_end = (dash.tick_to_end - 1).max(0);
+                dash.tick_to_vulnerable = (dash.tick_to_vulnerable - 1).max(0);
+                dash.tick_to_ready = (dash.tick_to_ready - 1).max(0);
+
+                // рывок идёт туда, куда игрок движется, стоя на месте рвануть нельзя
+                let direction = match target.try_normalize(0.0001) {
+                    Some(direction) => Some(direction),
+                    None => vel.velocity.try_normalize(0.0001),
+                };
+                if let (true, true, Some(direction)) = (
+                    is_dash_requested && can_act,
+                    dash.tick_to_ready <= 0,
+                    direction,
+                ) {
+                    dash.direction = direction;
+                    dash.tick_to_end = DASH_TICKS;
+                    dash.tick_to_vulnerable = DASH_INVULNERABLE_TICKS;
+                    dash.tick_to_ready = DASH_COOLDOWN_TICKS;
+                }
+
+                if dash.is_dashing() {
+                    vel.velocity = dash.direction * player.max_speed * DASH_SPEED_MULTIPLIER
+                        / settings.fps as f32;
+                    continue;
+                }
+            }
+
+            vel.velocity =
+                get_inertial_velocity(&vel.velocity, &target, &settings.movement, settings.fps);
+        }
+    }
+}
+
+// мёртвая зона гасит дрожание стика, кривая даёт точность при малых отклонениях
+fn get_player_target_velocity(
+    direction: &Vector,
+    max_speed: f32,
+    movement: &MovementConfig,
+) -> Vector {
+    let magnitude = direction.norm().min(1.);
+    if magnitude <= movement.dead_zone {
+        return Vector::zeros();
+    }
+
+    let response = ((magnitude - movement.dead_zone) / (1. - movement.dead_zone))
+        .powf(movement.response_curve);
+    direction.normalize() * response * max_speed
+}
+
+// скорость за тик меняется не больше, чем позволяет ускорение или торможение
+fn get_inertial_velocity(
+    velocity: &Vector,
+    target: &Vector,
+    movement: &MovementConfig,
+    fps: i32,
+) -> Vector {
+    let rate = match target.norm() >= velocity.norm() {
+        true => movement.acceleration,
+        false => movement.deceleration,
+    };
+    let step = rate / (fps * fps) as f32;
+
+    let delta = target - velocity;
+    match delta.norm() <= step {
+        true => *target,
+        false => velocity + delta.normalize() * step,
+    }
+}
+
+struct PlayerPositionSystem;
+
+impl<'a> System<'a> for PlayerPositionSystem {
+    type SystemData = (
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Velocity>,
+        ReadStorage<'a, Player>,
+    );
+
+    fn run(&mut self, (mut pos_storage, vel_storage, player_storage): Self::SystemData) {
+        use specs::Join;
+
+        for (p, v, _) in (&mut pos_storage, &vel_storage, &player_storage).join() {
+            p.point += v.velocity;
+        }
+    }
+}
+
+struct EnemyAiSystem;
+impl<'a> System<'a> for EnemyAiSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Polarity>,
+        ReadStorage<'a, Color>,
+        ReadStorage<'a, Health>,
+        WriteStorage<'a, Ai>,
+        Write<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            pos_storage,
+            player_storage,
+            polarity_storage,
+            color_storage,
+            health_storage,
+            mut ai_storage,
+            mut settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let (player_pos, player_sign) =
+            match (&pos_storage, &player_storage, polarity_storage.maybe())
+                .join()
+                .next()
+            {
+                Some((pos, _, polarity)) => (pos.point, polarity.map_or(0, Polarity::get_sign)),
+                None => return,
+            };
+
+        for (pos, color, health, ai) in (
+            &pos_storage,
+            &color_storage,
+            health_storage.maybe(),
+            &mut ai_storage,
+        )
+            .join()
+        {
+            ai.ticks_in_state += 1;
+            if ai.tick_to_charge > 0 {
+                ai.tick_to_charge -= 1;
+            }
+
+            let config = ai.config.clone();
+            let to_player = settings.boundary.get_offset(&pos.point, &player_pos);
+            let distance = to_player.norm();
+            let is_threatened =
+                config.flee_matching_color && is_overcolored(color.is_white, player_sign);
+            let is_wounded = match (config.retreat_health, health) {
+                (Some(threshold), Some(h)) => (h.hp as f32 / h.max_hp.max(1) as f32) < threshold,
+                _ => false,
+            };
+
+            // рывок нельзя прервать, иначе его нельзя будет прочитать по замаху
+            let next_state = match ai.state {
+                AiState::ChargeWindup => match config.charge {
+                    Some(c) if ai.ticks_in_state < c.windup_ticks => AiState::ChargeWindup,
+                    _ => AiState::Charge,
+                },
+                AiState::Charge => match config.charge {
+                    Some(c) if ai.ticks_in_state < c.dash_ticks => AiState::Charge,
+                    _ => AiState::Chase,
+                },
+                AiState::Retreat if ai.ticks_in_state < config.retreat_ticks => AiState::Retreat,
+                _ if is_threatened && distance < config.sight_radius => AiState::Flee,
+                _ if is_wounded && !ai.has_retreated => AiState::Retreat,
+                _ if distance > config.sight_radius => AiState::Wander,
+                _ => match config.charge {
+                    Some(c) if distance < c.range && ai.tick_to_charge == 0 => {
+                        AiState::ChargeWindup
+                    }
+                    _ => match config.flank_distance {
+                        Some(flank) if distance > flank => AiState::Flank,
+                        _ => AiState::Chase,
+                    },
+                },
+            };
+
+            if next_state == AiState::Charge && ai.state == AiState::ChargeWindup {
+                // направление рывка фиксируется в конце замаха
+                ai.target = Some(player_pos + to_player);
+            }
+            if next_state == AiState::Chase && ai.state == AiState::Charge {
+                ai.tick_to_charge = config.charge.map_or(0, |c| c.cooldown_ticks);
+            }
+            if next_state == AiState::Retreat {
+                ai.has_retreated = true;
+            }
+            ai.set_state(next_state);
+
+            ai.speed_multiplier = 1.;
+            match ai.state {
+                AiState::Wander => {
+                    if ai.target.is_none() || ai.ticks_in_state % config.wander_ticks.max(1) == 0 {
+                        let angle = settings.rnd.gen::<f32>() * std::f32::consts::PI * 2.;
+                        ai.target = Some(pos.point + Vector::new(angle.cos(), angle.sin()) * 5.);
+                    }
+                    ai.speed_multiplier = 0.5;
+                }
+                AiState::Chase => ai.target = Some(player_pos),
+                AiState::Flank => {
+                    let flank = config.flank_distance.unwrap_or(0.);
+                    let side = Vector::new(-to_player.y, to_player.x)
+                        .try_normalize(0.001)
+                        .unwrap_or_else(Vector::zeros);
+                    ai.target = Some(player_pos + side * flank);
+                }
+                AiState::ChargeWindup => ai.target = None,
+                AiState::Charge => {
+                    ai.speed_multiplier = config.charge.map_or(1., |c| c.speed_multiplier);
+                }
+                AiState::Retreat | AiState::Flee => ai.target = Some(pos.point - to_player),
+            }
+        }
+    }
+}
+
+// враги с оружием, но без дерева поведения, сами целятся в игрока, когда он в зоне поражения
+struct EnemyGunSystem;
+impl<'a> System<'a> for EnemyGunSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Gun>,
+        ReadStorage<'a, Behavior>,
+        ReadStorage<'a, Ally>,
+        WriteStorage<'a, Trigger>,
+        Write<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            pos_storage,
+            player_storage,
+            gun_storage,
+            behavior_storage,
+            ally_storage,
+            mut trigger_storage,
+            mut settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player_pos = match (&pos_storage, &player_storage).join().next() {
+            Some((pos, _)) => pos.point,
+            None => return,
+        };
+
+        for (pos, gun, trigger, _, _) in (
+            &pos_storage,
+            &gun_storage,
+            &mut trigger_storage,
+            !&behavior_storage,
+            !&ally_storage,
+        )
+            .join()
+        {
+            let offset = settings.boundary.get_offset(&pos.point, &player_pos);
+            if offset.norm() > gun.config.range {
+                continue;
+            }
+
+            let error = match gun.config.aim_error > 0. {
+                true => (settings.rnd.gen::<f32>() * 2. - 1.) * gun.config.aim_error,
+                false => 0.,
+            };
+            if let Some(direction) = math::rotate(&offset, error).try_normalize(0.001) {
+                trigger.target = Some(pos.point + direction * gun.config.range);
+            }
+        }
+    }
+}
+
+// дроны следуют за игроком, турели ставятся по запросу, и каждый союзник сам выбирает цель
+struct AllySystem;
+impl<'a> System<'a> for AllySystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Polarity>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Color>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Gun>,
+        WriteStorage<'a, Ally>,
+        WriteStorage<'a, Orbit>,
+        WriteStorage<'a, Trigger>,
+        ReadStorage<'a, DeadByTtl>,
+        Write<'a, Input>,
+        Read<'a, Settings>,
+        Read<'a, LazyUpdate>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut pos_storage,
+            player_storage,
+            polarity_storage,
+            enemy_storage,
+            color_storage,
+            health_storage,
+            gun_storage,
+            mut ally_storage,
+            mut orbit_storage,
+            mut trigger_storage,
+            ttl_storage,
+            mut input,
+            settings,
+            lazy,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        if let Some(point) = input.turret_point.take() {
+            // сверх лимита исчезает турель, которой осталось жить меньше всех
+            let mut turrets = (&entities, &ally_storage, &ttl_storage)
+                .join()
+                .filter(|(_, ally, _)| ally.kind == AllyKind::Turret)
+                .map(|(e, _, ttl)| (e, ttl.ttl))
+                .collect::<Vec<_>>();
+            turrets.sort_by_key(|(_, ttl)| *ttl);
+            for (turret, _) in turrets
+                .iter()
+                .take((turrets.len() + 1).saturating_sub(MAX_TURRETS))
+            {
+                entities.delete(*turret).unwrap();
+            }
+
+            let turret = entities.create();
+            lazy.insert(
+                turret,
+                Ally {
+                    kind: AllyKind::Turret,
+                    rule: TargetRule::LowestHealth,
+                    target: None,
+                },
+            );
+            lazy.insert(turret, Position { point });
+            lazy.insert(turret, Gun::new(get_turret_gun()));
+            lazy.insert(turret, Trigger::default());
+            lazy.insert(turret, DeadByTtl { ttl: TURRET_TTL });
+        }
+
+        // без игрока союзники замирают и не стреляют
+        let player_pos = match (&pos_storage, &player_storage).join().next() {
+            Some((pos, _)) => pos.point,
+            None => {
+                for (ally, trigger) in (&mut ally_storage, &mut trigger_storage).join() {
+                    ally.target = None;
+                    trigger.target = None;
+                }
+                return;
+            }
+        };
+
+        for (orbit, pos) in (&mut orbit_storage, &mut pos_storage).join() {
+            orbit.angle = (orbit.angle + orbit.angular_speed / settings.fps as f32)
+                % (2. * std::f32::consts::PI);
+            pos.point = settings.boundary.wrap(
+                &(player_pos + Vector::new(orbit.angle.cos(), orbit.angle.sin()) * orbit.radius),
+            );
+        }
+
+        let candidates = (
+            &entities,
+            &pos_storage,
+            &enemy_storage,
+            color_storage.maybe(),
+            health_storage.maybe(),
+        )
+            .join()
+            .map(|(entity, pos, _, color, health)| AllyTarget {
+                entity,
+                point: pos.point,
+                is_white: color.map(|c| c.is_white),
+                hp: health.map_or(1, |h| h.hp),
+            })
+            .collect::<Vec<_>>();
+        let player_sign = (&player_storage, &polarity_storage)
+            .join()
+            .next()
+            .map_or(0, |(_, polarity)| polarity.get_sign());
+
+        for (pos, gun, ally, trigger) in (
+            &pos_storage,
+            &gun_storage,
+            &mut ally_storage,
+            &mut trigger_storage,
+        )
+            .join()
+        {
+            let target = get_ally_target(
+                ally.rule,
+                &pos.point,
+                gun.config.range,
+                player_sign,
+                &candidates,
+                &settings.boundary,
+            );
+            ally.target = target.map(|t| t.entity);
+            trigger.target =
+                target.map(|t| pos.point + settings.boundary.get_offset(&pos.point, &t.point));
+        }
+    }
+}
+
+struct AllyTarget {
+    entity: Entity,
+    point: Point,
+    is_white: Option<bool>,
+    hp: i32,
+}
+
+// врагов без здоровья убивает одно попадание, поэтому для них здоровье считается единицей
+fn get_ally_target<'c>(
+    rule: TargetRule,
+    from: &Point,
+    range: f32,
+    force: i32,
+    candidates: &'c [AllyTarget],
+    boundary: &Boundary,
+) -> Option<&'c AllyTarget> {
+    let distance = |t: &AllyTarget| boundary.get_offset(from, &t.point).norm();
+    let by_distance = |a: &&AllyTarget, b: &&AllyTarget| {
+        distance(a)
+            .partial_cmp(&distance(b))
+            .unwrap_or(Ordering::Equal)
+    };
+    let in_range = candidates.iter().filter(|t| distance(t) <= range);
+
+    match rule {
+        TargetRule::Nearest => in_range.min_by(by_distance),
+        TargetRule::MatchingColor => in_range
+            .filter(|t| {
+                t.is_white
+                    .is_some_and(|is_white| is_overcolored(is_white, force))
+            })
+            .min_by(by_distance),
+        TargetRule::LowestHealth => {
+            in_range.min_by(|a, b| a.hp.cmp(&b.hp).then_with(|| by_distance(a, b)))
+        }
+    }
+}
+
+struct BehaviorTreeSystem;
+impl<'a> System<'a> for BehaviorTreeSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Gun>,
+        WriteStorage<'a, Trigger>,
+        WriteStorage<'a, Behavior>,
+        Write<'a, SpawnQueue>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            pos_storage,
+            player_storage,
+            health_storage,
+            gun_storage,
+            mut trigger_storage,
+            mut behavior_storage,
+            mut spawn_queue,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player_pos = (&pos_storage, &player_storage)
+            .join()
+            .next()
+            .map(|(pos, _)| pos.point);
+
+        for (entity, pos, health, behavior) in (
+            &entities,
+            &pos_storage,
+            health_storage.maybe(),
+            &mut behavior_storage,
+        )
+            .join()
+        {
+            // на торе дерево видит ближайшую через край копию игрока
+            let mut agent = EntityAgent {
+                pos: pos.point,
+                player_pos: player_pos
+                    .map(|p| pos.point + settings.boundary.get_offset(&pos.point, &p)),
+                health_fraction: health.map_or(1., |h| h.hp as f32 / h.max_hp.max(1) as f32),
+                has_gun: gun_storage.contains(entity),
+                target: None,
+                speed_multiplier: 1.,
+                shoot_at: None,
+                spawns: vec![],
+            };
+
+            behavior.tree.tick(&mut agent);
+
+            behavior.target = agent.target;
+            behavior.speed_multiplier = agent.speed_multiplier;
+
+            if let (Some(shoot_at), Some(trigger)) =
+                (agent.shoot_at, trigger_storage.get_mut(entity))
+            {
+                trigger.target = Some(shoot_at);
+            }
+
+            for request in agent.spawns {
+                spawn_queue.requests.push(request);
+            }
+        }
+    }
+}
+
+struct EntityAgent {
+    pos: Point,
+    player_pos: Option<Point>,
+    health_fraction: f32,
+    has_gun: bool,
+    target: Option<Point>,
+    speed_multiplier: f32,
+    shoot_at: Option<Point>,
+    spawns: Vec<SpawnRequest>,
+}
+
+impl behavior::Agent for EntityAgent {
+    fn distance_to_player(&self) -> Option<f32> {
+        self.player_pos.map(|p| na::distance(&p, &self.pos))
+    }
+
+    fn health_fraction(&self) -> f32 {
+        self.health_fraction
+    }
+
+    fn move_to(
+        &mut self,
+        target: MoveTarget,
+        speed_multiplier: f32,
+        arrive_distance: f32,
+    ) -> behavior::Status {
+        let target = match (target, self.player_pos) {
+            (MoveTarget::Player, Some(player)) => player,
+            (MoveTarget::AwayFromPlayer, Some(player)) => self.pos + (self.pos - player),
+            (MoveTarget::NearPlayer { x, y }, Some(player)) => player + Vector::new(x, y),
+            (MoveTarget::Point { x, y }, _) => Point::new(x, y),
+            (_, None) => return behavior::Status::Failure,
+        };
+
+        if na::distance(&target, &self.pos) <= arrive_distance {
+            self.target = None;
+            return behavior::Status::Success;
+        }
+
+        self.target = Some(target);
+        self.speed_multiplier = speed_multiplier;
+        behavior::Status::Running
+    }
+
+    fn shoot(&mut self) -> behavior::Status {
+        match (self.has_gun, self.player_pos) {
+            (true, Some(player)) => {
+                self.shoot_at = Some(player);
+                behavior::Status::Success
+            }
+            _ => behavior::Status::Failure,
+        }
+    }
+
+    fn spawn(&mut self, archetype: &EnemyArchetype, count: u32, pattern: Option<SpawnPattern>) {
+        self.spawns.push(SpawnRequest {
+            pattern: pattern.unwrap_or(SpawnPattern::At {
+                x: self.pos.x,
+                y: self.pos.y,
+            }),
+            count,
+            archetype: archetype.clone(),
+            spawner: None,
+            formation: None,
+        });
+    }
+}
+
+struct EnemiesVelocitySystem;
+impl<'a> System<'a> for EnemiesVelocitySystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Steering>,
+        ReadStorage<'a, Ai>,
+        ReadStorage<'a, Behavior>,
+        ReadStorage<'a, Spawner>,
+        ReadStorage<'a, Obstacle>,
+        ReadStorage<'a, Flock>,
+        ReadStorage<'a, FormationMember>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Velocity>,
+        Read<'a, Settings>,
+        Read<'a, Pathfinding>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            pos_storage,
+            player_storage,
+            enemy_storage,
+            steering_storage,
+            ai_storage,
+            behavior_storage,
+            spawner_storage,
+            obstacle_storage,
+            flock_storage,
+            formation_storage,
+            status_storage,
+            mut vel_storage,
+            settings,
+            pathfinding,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player = (&pos_storage, &player_storage).join().next();
+
+        if let Some((p_pos, _)) = player {
+            let neighbors = (
+                &entities,
+                &pos_storage,
+                &vel_storage,
+                &enemy_storage,
+                flock_storage.maybe(),
+            )
+                .join()
+                .map(|(entity, pos, vel, enemy, flock)| Neighbor {
+                    entity,
+                    point: pos.point,
+                    velocity: vel.velocity,
+                    radius: enemy.radius,
+                    flock: flock.map(|f| f.group),
+                })
+                .collect::<Vec<_>>();
+
+            // ведущий строя - живой участник с наименьшим номером слота
+            let mut leaders: HashMap<u32, (usize, Point)> = HashMap::new();
+            for (pos, member) in (&pos_storage, &formation_storage).join() {
+                let leader = leaders
+                    .entry(member.group)
+                    .or_insert((member.slot, pos.point));
+                if member.slot < leader.0 {
+                    *leader = (member.slot, pos.point);
+                }
+            }
+
+            let spawners = (&pos_storage, &spawner_storage)
+                .join()
+                .map(|(pos, spawner)| (pos.point, spawner.radius))
+                .collect::<Vec<_>>();
+            let obstacles = (&pos_storage, &obstacle_storage)
+                .join()
+                .map(|(pos, obstacle)| (pos.point, obstacle.get_bounding_radius()))
+                .chain(spawners.iter().cloned())
+                .collect::<Vec<_>>();
+            let walls = (&pos_storage, &obstacle_storage)
+                .join()
+                .map(|(pos, obstacle)| (pos.point, obstacle))
+                .collect::<Vec<_>>();
+
+            for (entity, e_pos, e_vel, e, steering, ai, behavior, flock, formation) in (
+                &entities,
+                &pos_storage,
+                &mut vel_storage,
+                &enemy_storage,
+                steering_storage.maybe(),
+                ai_storage.maybe(),
+                behavior_storage.maybe(),
+                flock_storage.maybe(),
+                formation_storage.maybe(),
+            )
+                .join()
+            {
+                let (target, speed_multiplier) = match (behavior, ai, formation) {
+                    (Some(b), _, _) => (b.target, b.speed_multiplier),
+                    (None, Some(ai), _) => (ai.target, ai.speed_multiplier),
+                    (None, None, Some(member)) => {
+                        let (target, speed_multiplier) = get_formation_target(
+                            member,
+                            &e_pos.point,
+                            &p_pos.point,
+                            &leaders,
+                            &settings.boundary,
+                        );
+                        (Some(target), speed_multiplier)
+                    }
+                    (None, None, None) => (Some(p_pos.point), 1.),
+                };
+                let target = match target {
+                    Some(target) => target,
+                    None => {
+                        e_vel.velocity = Vector::zeros();
+                        continue;
+                    }
+                };
+
+                let me = Neighbor {
+                    entity,
+                    point: e_pos.point,
+                    velocity: e_vel.velocity,
+                    radius: e.radius,
+                    flock: flock.map(|f| f.group),
+                };
+                let is_chasing = target == p_pos.point;
+                // на торе к цели идут кратчайшим путём, в том числе через край
+                let target = e_pos.point + settings.boundary.get_offset(&e_pos.point, &target);
+                // игрока за препятствием догоняют по полю пути, а не упираются в стену;
+                // поле уже обходит препятствия, их круги только сбивали бы с пути
+                let (target, obstacles) = match pathfinding.field.get_direction(&e_pos.point) {
+                    Some(direction)
+                        if is_chasing
+                            && get_obstacle_hit(&e_pos.point, &target, &walls).is_some() =>
+                    {
+                        (e_pos.point + direction, &spawners)
+                    }
+                    _ => (target, &obstacles),
+                };
+                let steering = steering.cloned().unwrap_or_default();
+                let direction =
+                    get_steering_direction(&me, &target, &steering, &neighbors, obstacles);
+
+                let speed_multiplier = speed_multiplier
+                    * status_storage
+                        .get(entity)
+                        .map_or(1., |s| s.get_speed_multiplier());
+                e_vel.velocity = match direction {
+                    Some(d) => d * e.max_speed * speed_multiplier / settings.fps as f32,
+                    None => Vector::zeros(),
+                }
+            }
+        }
+    }
+}
+
+struct Neighbor {
+    entity: Entity,
+    point: Point,
+    velocity: Vector,
+    radius: f32,
+    flock: Option<u32>,
+}
+
+// пока ведущий далеко от игрока, остальные держат свои места относительно него
+fn get_formation_target(
+    member: &FormationMember,
+    point: &Point,
+    player: &Point,
+    leaders: &HashMap<u32, (usize, Point)>,
+    boundary: &Boundary,
+) -> (Point, f32) {
+    let (leader_slot, leader_point) = match leaders.get(&member.group) {
+        Some(leader) => *leader,
+        None => return (*player, 1.),
+    };
+
+    let to_player = boundary.get_offset(&leader_point, player);
+    let heading = match to_player.try_normalize(0.001) {
+        Some(heading) => heading,
+        None => return (*player, 1.),
+    };
+    if to_player.norm() < member.def.break_distance {
+        return (*player, 1.);
+    }
+
+    // ведущий идёт медленнее, чтобы отставшие успели занять места
+    if member.slot == leader_slot {
+        return (*player, FORMATION_LEADER_PACE);
+    }
+
+    let offset = member.get_offset(member.slot) - member.get_offset(leader_slot);
+    let rotated = Vector::new(
+        offset.x * heading.x - offset.y * heading.y,
+        offset.x * heading.y + offset.y * heading.x,
+    );
+    let slot = leader_point + rotated;
+    let lag = boundary.get_offset(point, &slot).norm() / member.def.spacing;
+
+    (slot, (FORMATION_LEADER_PACE + lag).min(1.5))
+}
+
+const FORMATION_LEADER_PACE: f32 = 0.75;
+
+fn get_steering_direction(
+    me: &Neighbor,
+    target: &Point,
+    steering: &Steering,
+    neighbors: &[Neighbor],
+    obstacles: &[(Point, f32)],
+) -> Option<Vector> {
+    let seek = (target - me.point)
+        .try_normalize(0.001)
+        .unwrap_or_else(Vector::zeros);
+
+    let mut separation = Vector::zeros();
+    let mut alignment = Vector::zeros();
+    let mut flock_center = Vector::zeros();
+    let mut flock_size = 0;
+    for other in neighbors.iter().filter(|n| n.entity != me.entity) {
+        let offset = me.point - other.point;
+        let distance = offset.norm();
+
+        // стая выравнивается и стягивается только со своими, но в большем радиусе
+        let is_flockmate = me.flock.is_some() && me.flock == other.flock;
+        if is_flockmate && distance < steering.flock_radius {
+            if let Some(heading) = other.velocity.try_normalize(0.001) {
+                alignment += heading;
+            }
+            flock_center += other.point.coords;
+            flock_size += 1;
+        }
+
+        let radius = steering.neighbor_radius + me.radius + other.radius;
+        if distance >= radius {
+            continue;
+        }
+
+        // чем ближе сосед, тем сильнее отталкиваемся
+        if let Some(away) = offset.try_normalize(0.001) {
+            separation += away * (1. - distance / radius);
+        }
+        if me.flock.is_none() {
+            if let Some(heading) = other.velocity.try_normalize(0.001) {
+                alignment += heading;
+            }
+        }
+    }
+
+    let cohesion = match flock_size {
+        0 => Vector::zeros(),
+        _ => (flock_center / flock_size as f32 - me.point.coords) / steering.flock_radius,
+    };
+
+    let mut avoidance = Vector::zeros();
+    for (center, obstacle_radius) in obstacles {
+        let offset = me.point - center;
+        let clearance = offset.norm() - obstacle_radius - me.radius;
+        let is_ahead = seek.dot(&-offset) > 0.;
+        if clearance < steering.neighbor_radius && is_ahead {
+            // обходим препятствие по касательной, с той стороны, куда ближе к цели
+            let tangent = Vector::new(-offset.y, offset.x);
+            let tangent = match tangent.dot(&seek) >= 0. {
+                true => tangent,
+                false => -tangent,
+            };
+            let strength = 1. - clearance.max(0.) / steering.neighbor_radius;
+            avoidance += (tangent.try_normalize(0.001).unwrap_or_else(Vector::zeros)
+                + offset.try_normalize(0.001).unwrap_or_else(Vector::zeros))
+                * strength;
+        }
+    }
+
+    let direction = seek * steering.seek
+        + separation * steering.separation
+        + alignment.try_normalize(0.001).unwrap_or_else(Vector::zeros) * steering.alignment
+        + cohesion * steering.cohesion
+        + avoidance * steering.avoidance;
+
+    direction.try_normalize(0.001)
+}
+
+// поле пути к игроку и масштаб арены, под который размечены закрытые клетки
+pub struct Pathfinding {
+    pub field: FlowField,
+    arena_scale: f32,
+}
+
+impl Default for Pathfinding {
+    fn default() -> Self {
+        Pathfinding {
+            field: FlowField::default(),
+            arena_scale: 1.,
+        }
+    }
+}
+
+const FLOW_CELL_SIZE: f32 = 1.;
+const FLOW_CELLS_PER_TICK: usize = 500;
+const FLOW_CLEARANCE: f32 = 0.4;
+
+// поле перестраивается, когда игрок переходит в другую клетку или сжимается арена
+struct FlowFieldSystem;
+impl<'a> System<'a> for FlowFieldSystem {
+    type SystemData = (
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Obstacle>,
+        Read<'a, Settings>,
+        Write<'a, Pathfinding>,
+    );
+
+    fn run(
+        &mut self,
+        (pos_storage, player_storage, obstacle_storage, settings, mut pathfinding): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player_pos = match (&pos_storage, &player_storage).join().next() {
+            Some((pos, _)) => pos.point,
+            None => return,
+        };
+
+        let boundary = &settings.boundary;
+        let width = (boundary.size.x / FLOW_CELL_SIZE).ceil() as usize;
+        let height = (boundary.size.y / FLOW_CELL_SIZE).ceil() as usize;
+        if pathfinding.field.width != width || pathfinding.field.height != height {
+            pathfinding.field = FlowField::new(
+                Point::origin(),
+                FLOW_CELL_SIZE,
+                width,
+                height,
+                boundary.shape == ArenaShape::Wrap,
+            );
+        }
+
+        // волна всегда доходит до конца: игрок и сжатие меняются каждый тик,
+        // и если перезапускать её на ходу, поле так и не достроится
+        let target = pathfinding.field.get_cell(&player_pos);
+        let is_moved = target != pathfinding.field.target;
+        let is_shrunk = boundary.scale != pathfinding.arena_scale;
+        let is_stale = (is_moved || is_shrunk) && !pathfinding.field.is_building();
+        if let (Some(target), true) = (target, is_stale) {
+            let obstacles = (&pos_storage, &obstacle_storage).join().collect::<Vec<_>>();
+            let field = &pathfinding.field;
+            let blocked = (0..width * height)
+                .map(|cell| {
+                    let center = field.get_cell_center(cell);
+                    !boundary.contains(&center)
+                        || obstacles.iter().any(|(pos, obstacle)| {
+                            obstacle
+                                .get_push_out(&pos.point, &center, FLOW_CLEARANCE)
+                                .is_some()
+                        })
+                })
+                .collect();
+            pathfinding.field.restart(target, blocked);
+            pathfinding.arena_scale = boundary.scale;
+        }
+
+        pathfinding.field.step(FLOW_CELLS_PER_TICK);
+    }
+}
+
+// игрок и враги не проходят сквозь препятствия, а скользят вдоль них
+struct ObstacleCollisionSystem;
+impl<'a> System<'a> for ObstacleCollisionSystem {
+    type SystemData = (
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Velocity>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Obstacle>,
+    );
+
+    fn run(
+        &mut self,
+        (mut pos_storage, mut vel_storage, player_storage, enemy_storage, obstacle_storage): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let obstacles = (&pos_storage, &obstacle_storage)
+            .join()
+            .map(|(pos, obstacle)| (pos.point, obstacle))
+            .collect::<Vec<_>>();
+
+        for (pos, mut vel, player, enemy) in (
+            &mut pos_storage,
+            (&mut vel_storage).maybe(),
+            player_storage.maybe(),
+            enemy_storage.maybe(),
+        )
+            .join()
+        {
+            let radius = match (player, enemy) {
+                (Some(player), _) => player.radius,
+                (None, Some(enemy)) => enemy.radius,
+                (None, None) => continue,
+            };
+
+            for (center, obstacle) in obstacles.iter() {
+                let push = match obstacle.get_push_out(center, &pos.point, radius) {
+                    Some(push) => push,
+                    None => continue,
+                };
+                pos.point += push;
+
+                if let (Some(vel), Some(normal)) = (vel.as_mut(), push.try_normalize(0.001)) {
+                    let into = vel.velocity.dot(&normal);
+                    if into < 0. {
+                        vel.velocity -= normal * into;
+                    }
+                }
+            }
+        }
+    }
+}
+
+struct EnemiesPositionSystem;
+impl<'a> System<'a> for EnemiesPositionSystem {
+    type SystemData = (
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Velocity>,
+    );
+
+    fn run(&mut self, (mut pos_storage, enemy_storage, vel_storage): Self::SystemData) {
+        use specs::Join;
+        let mut enemies = (&mut pos_storage, &vel_storage, &enemy_storage)
+            .join()
+            .collect::<Vec<_>>();
+
+        for enemy_id in 0..enemies.len() {
+            let movement = {
+                let (e_pos, e_vel, enemy) = &enemies[enemy_id];
+                let mut movement = e_vel.velocity;
+                let new_pos = e_pos.point + movement;
+
+                // вместо остановки при столкновении скользим вдоль соседа
+                for (ae_pos, _, e) in enemies.iter() {
+                    let min_distance = e.radius + enemy.radius;
+                    if std::ptr::eq(*e, *enemy)
+                        || !has_circles_collision(&ae_pos.point, &new_pos, min_distance)
+                    {
+                        continue;
+                    }
+
+                    let offset = e_pos.point - ae_pos.point;
+                    if let Some(normal) = offset.try_normalize(0.001) {
+                        let into = movement.dot(&normal);
+                        if into < 0. {
+                            movement -= normal * into;
+                        }
+
+                        let overlap = min_distance - offset.norm();
+                        if overlap > 0. {
+                            movement += normal * overlap.min(e_vel.velocity.norm()) / 2.;
+                        }
+                    }
+                }
+
+                movement
+            };
+
+            enemies[enemy_id].0.point += movement;
+        }
+    }
+}
+
+struct GunShotSystem;
+impl<'a> System<'a> for GunShotSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Gun>,
+        WriteStorage<'a, Trigger>,
+        WriteStorage<'a, Shot>,
+        WriteStorage<'a, Projectile>,
+        WriteStorage<'a, DeadByTtl>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Color>,
+        WriteStorage<'a, LockOn>,
+        WriteStorage<'a, HomingTarget>,
+        ReadStorage<'a, PowerUps>,
+        ReadStorage<'a, StatusEffects>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Ally>,
+        ReadStorage<'a, Polarity>,
+        specs::Read<'a, Input>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut gun_storage,
+            mut trigger_storage,
+            mut shot_storage,
+            mut projectile_storage,
+            mut ttl_storage,
+            mut pos_storage,
+            color_storage,
+            mut lock_storage,
+            mut homing_storage,
+            power_up_storage,
+            status_storage,
+            enemy_storage,
+            player_storage,
+            ally_storage,
+            polarity_storage,
+            input,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let player_sign = (&player_storage, &polarity_storage)
+            .join()
+            .next()
+            .map(|(_, polarity)| polarity.get_sign());
+
+        let mut shots = Vec::new();
+        for (entity, gun, pos, trigger) in (
+            &entities,
+            &mut gun_storage,
+            &pos_storage,
+            (&mut trigger_storage).maybe(),
+        )
+            .join()
+        {
+            let (shoot_point, force) = match trigger {
+                Some(trigger) => {
+                    let is_white = match gun.config.element {
+                        GunElement::White => Some(true),
+                        GunElement::Black => Some(false),
+                        GunElement::Own => {
+                            color_storage.get(entity).map(|c| c.is_white).or_else(|| {
+                                match (ally_storage.contains(entity), player_sign) {
+                                    (true, Some(sign)) => Some(sign > 0),
+                                    _ => None,
+                                }
+                            })
+                        }
+                    };
+                    let force = match is_white {
+                        Some(true) => gun.config.damage,
+                        Some(false) => -gun.config.damage,
+                        None => 0,
+                    };
+                    (trigger.target.take(), force)
+                }
+                None => {
+                    let sign = polarity_storage.get(entity).map_or(1, Polarity::get_sign);
+                    (
+                        input.shoot_point,
+                        sign * input.shoot_force * gun.config.damage,
+                    )
+                }
+            };
+            let is_hostile = enemy_storage.contains(entity);
+
+            gun.update();
+
+            if status_storage.get(entity).is_some_and(|s| !s.can_act()) {
+                gun.charge = 0;
+                gun.burst_left = 0;
+                continue;
+            }
+
+            let boost = |config: &GunConfig| match power_up_storage.get(entity) {
+                Some(power_ups) => power_ups.boost(config),
+                None => *config,
+            };
+
+            // очередь достреливается и после отпускания курка, пока есть патроны
+            if gun.burst_left > 0 && gun.is_jammed() {
+                gun.burst_left = 0;
+            }
+            if gun.burst_left > 0 {
+                gun.tick_to_burst -= 1;
+                if gun.tick_to_burst <= 0 {
+                    gun.burst_left -= 1;
+                    gun.tick_to_burst = gun.config.burst_interval;
+                    if let Some(aim) = gun.aim {
+                        let config = boost(&gun.config);
+                        shots.extend(get_gun_shots(
+                            entity, &config, &pos.point, &aim, force, is_hostile,
+                        ));
+                        gun.on_fired();
+                    }
+                }
+            }
+
+            // заряжаемое оружие копит заряд, пока зажат курок, и стреляет при отпускании
+            let (fire_at, force, config) = match (gun.config.charge_ticks, shoot_point) {
+                (0, point) => (point, force, gun.config),
+                (charge_ticks, Some(point)) => {
+                    gun.aim = Some(point);
+                    gun.charge = (gun.charge + 1).min(charge_ticks);
+                    (None, force, gun.config)
+                }
+                // отпущенный заряд не пропадает, а ждёт конца перезарядки или остывания
+                (_, None) => {
+                    let level = gun.get_charge_level();
+                    if gun.is_ready() {
+                        gun.charge = 0;
+                    }
+                    (
+                        gun.aim.filter(|_| level > 0.),
+                        gun.config.get_charged_force(force, level),
+                        gun.config.charged(level),
+                    )
+                }
+            };
+
+            if let (Some(point), true) = (fire_at, gun.is_ready()) {
+                let config = boost(&config);
+                gun.aim = Some(point);
+                shots.extend(get_gun_shots(
+                    entity, &config, &pos.point, &point, force, is_hostile,
+                ));
+                gun.on_fired();
+                gun.tick_to_reload = config.reload_ticks;
+                gun.burst_left = gun.config.burst.max(1) - 1;
+                gun.tick_to_burst = gun.config.burst_interval;
+            }
+        }
+
+        for (shot, projectile) in shots {
+            match projectile {
+                Some(config) => {
+                    let direction = (shot.to - shot.from)
+                        .try_normalize(0.001)
+                        .unwrap_or_else(Vector::zeros);
+                    let homing = match config.turn_rate > 0. {
+                        true => {
+                            let locked = shot
+                                .owner
+                                .and_then(|o| lock_storage.get_mut(o))
+                                .and_then(|lock| lock.next_target());
+                            let target = locked.or_else(|| {
+                                let targets = get_homing_candidates(
+                                    shot.is_hostile,
+                                    &entities,
+                                    &pos_storage,
+                                    &enemy_storage,
+                                    &player_storage,
+                                );
+                                get_nearest(&shot.to, &targets, &settings.boundary).map(|(e, _)| e)
+                            });
+                            Some(HomingTarget {
+                                target,
+                                turn_rate: config.turn_rate,
+                            })
+                        }
+                        false => None,
+                    };
+                    let projectile = entities
+                        .build_entity()
+                        .with(Position { point: shot.from }, &mut pos_storage)
+                        .with(
+                            Projectile {
+                                velocity: direction * config.speed / settings.fps as f32,
+                                radius: config.radius,
+                                force: shot.force,
+                                owner: shot.owner,
+                                is_hostile: shot.is_hostile,
+                                recolor: shot.recolor,
+                                blast_radius: shot.blast_radius,
+                                status: shot.status,
+                            },
+                            &mut projectile_storage,
+                        )
+                        .with(
+                            DeadByTtl {
+                                ttl: config.lifetime,
+                            },
+                            &mut ttl_storage,
+                        )
+                        .build();
+                    if let Some(homing) = homing {
+                        homing_storage.insert(projectile, homing).unwrap();
+                    }
+                }
+                None => {
+                    entities
+                        .build_entity()
+                        .with(shot, &mut shot_storage)
+                        .with(DeadByTtl { ttl: 0 }, &mut ttl_storage)
+                        .build();
+                }
+            }
+        }
+    }
+}
+
+// дробь расходится веером на угол разброса
+fn get_gun_shots(
+    owner: Entity,
+    config: &GunConfig,
+    from: &Point,
+    to: &Point,
+    force: i32,
+    is_hostile: bool,
+) -> Vec<(Shot, Option<ProjectileConfig>)> {
+    let pellets = config.pellets.max(1);
+    (0..pellets)
+        .map(|i| {
+            let angle = match pellets {
+                1 => 0.,
+                _ => config.spread * (i as f32 / (pellets - 1) as f32 - 0.5),
+            };
+            let shot = Shot {
+                from: *from,
+                to: from + math::rotate(&(to - from), angle),
+                force,
+                owner: Some(owner),
+                is_hostile,
+                recolor: config.recolor_player,
+                radius: config.shot_radius,
+                projectile: None,
+                pierce: config.pierce,
+                ricochet: config.ricochet,
+                ignore: None,
+                blast_radius: config.blast_radius,
+                status: config.status.map(|kind| (kind, config.status_ticks)),
+            };
+            (shot, config.projectile)
+        })
+        .collect()
+}
+
+// вражеские ракеты летят в игрока, остальные во врагов
+fn get_homing_candidates(
+    is_hostile: bool,
+    entities: &specs::Entities,
+    pos_storage: &WriteStorage<Position>,
+    enemy_storage: &ReadStorage<Enemy>,
+    player_storage: &ReadStorage<Player>,
+) -> Vec<(Entity, Point)> {
+    use specs::Join;
+
+    match is_hostile {
+        true => (entities, pos_storage, player_storage)
+            .join()
+            .map(|(e, pos, _)| (e, pos.point))
+            .collect(),
+        false => (entities, pos_storage, enemy_storage)
+            .join()
+            .map(|(e, pos, _)| (e, pos.point))
+            .collect(),
+    }
+}
+
+fn get_nearest(
+    point: &Point,
+    candidates: &[(Entity, Point)],
+    boundary: &Boundary,
+) -> Option<(Entity, Point)> {
+    candidates
+        .iter()
+        .min_by(|(_, a), (_, b)| {
+            compare_vector_lengths(
+                &boundary.get_offset(point, a),
+                &boundary.get_offset(point, b),
+            )
+        })
+        .cloned()
+}
+
+struct LockOnSystem;
+impl<'a> System<'a> for LockOnSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, LockOn>,
+        ReadStorage<'a, Gun>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Position>,
+        Read<'a, Input>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut lock_storage, gun_storage, enemy_storage, pos_storage, input): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        for (lock, gun) in (&mut lock_storage, &gun_storage).join() {
+            if gun.config.lock_ticks <= 0 {
+                *lock = LockOn::default();
+                continue;
+            }
+
+            lock.targets
+                .retain(|t| entities.is_alive(*t) && enemy_storage.contains(*t));
+
+            let aimed = input.shoot_point.and_then(|point| {
+                (&entities, &enemy_storage, &pos_storage)
+                    .join()
+                    .find(|(_, enemy, pos)| {
+                        has_circles_collision(&point, &pos.point, enemy.radius + LOCK_AIM_TOLERANCE)
+                    })
+                    .map(|(e, _, _)| e)
+            });
+
+            lock.aim_ticks = match aimed.is_some() && aimed == lock.aimed {
+                true => lock.aim_ticks + 1,
+                false => 0,
+            };
+            lock.aimed = aimed;
+
+            if let Some(target) = aimed {
+                let is_locking = lock.aim_ticks >= gun.config.lock_ticks
+                    && lock.targets.len() < gun.config.max_locks
+                    && !lock.targets.contains(&target);
+                if is_locking {
+                    lock.targets.push(target);
+                }
+            }
+        }
+    }
+}
+
+const LOCK_AIM_TOLERANCE: f32 = 0.5;
+
+// снаряд за тик проходит отрезок, который проверяется как обычный выстрел,
+// поэтому быстрый снаряд не пролетает сквозь врага между тиками
+struct ProjectileSystem;
+impl<'a> System<'a> for ProjectileSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Projectile>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Shot>,
+        WriteStorage<'a, DeadByTtl>,
+        WriteStorage<'a, HomingTarget>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Player>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut projectile_storage,
+            mut pos_storage,
+            mut shot_storage,
+            mut ttl_storage,
+            mut homing_storage,
+            enemy_storage,
+            player_storage,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        // ракета доворачивает на цель, а если цель погибла - на ближайшую другую
+        for (projectile, homing, pos) in
+            (&mut projectile_storage, &mut homing_storage, &pos_storage).join()
+        {
+            let targets = get_homing_candidates(
+                projectile.is_hostile,
+                &entities,
+                &pos_storage,
+                &enemy_storage,
+                &player_storage,
+            );
+            let target = homing
+                .target
+                .and_then(|t| targets.iter().find(|(e, _)| *e == t).cloned())
+                .or_else(|| get_nearest(&pos.point, &targets, &settings.boundary));
+
+            homing.target = target.map(|(e, _)| e);
+            if let Some((_, point)) = target {
+                projectile.velocity = math::turn_towards(
+                    &projectile.velocity,
+                    &settings.boundary.get_offset(&pos.point, &point),
+                    homing.turn_rate / settings.fps as f32,
+                );
+            }
+        }
+
+        let mut shots = Vec::new();
+        for (entity, projectile, pos) in (&entities, &projectile_storage, &mut pos_storage).join() {
+            let from = pos.point;
+            let to = from + projectile.velocity;
+
+            // на торе снаряд перелетает через край, отрезок пути остаётся непрерывным
+            pos.point = settings.boundary.wrap(&to);
+            if !settings.boundary.contains(&pos.point) {
+                entities.delete(entity).unwrap();
+            }
+
+            shots.push(Shot {
+                from,
+                to,
+                force: projectile.force,
+                owner: projectile.owner,
+                is_hostile: projectile.is_hostile,
+                recolor: projectile.recolor,
+                radius: projectile.radius,
+                projectile: Some(entity),
+                pierce: 0,
+                ricochet: 0,
+                ignore: None,
+                blast_radius: projectile.blast_radius,
+                status: projectile.status,
+            });
+        }
+
+        for shot in shots {
+            entities
+                .build_entity()
+                .with(shot, &mut shot_storage)
+                .with(DeadByTtl { ttl: 0 }, &mut ttl_storage)
+                .build();
+        }
+    }
+}
+
+struct ShotSystem;
+impl<'a> System<'a> for ShotSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Shot>,
+        WriteStorage<'a, Color>,
+        WriteStorage<'a, Position>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Spawner>,
+        ReadStorage<'a, Boss>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, DeadByTtl>,
+        WriteStorage<'a, ShotTrace>,
+        WriteStorage<'a, Explosion>,
+        WriteStorage<'a, Polarity>,
+        WriteStorage<'a, Energy>,
+        ReadStorage<'a, Dash>,
+        WriteStorage<'a, StatusEffects>,
+        ReadStorage<'a, Obstacle>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut shot_storage,
+            mut color_storage,
+            mut pos_storage,
+            enemy_storage,
+            spawner_storage,
+            boss_storage,
+            player_storage,
+            mut health_storage,
+            mut ttl_storage,
+            mut trace_storage,
+            mut explosion_storage,
+            mut polarity_storage,
+            mut energy_storage,
+            dash_storage,
+            mut status_storage,
+            obstacle_storage,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        // выстрел обрабатывается один раз, хотя сущность удаляется только на следующем тике
+        let shots = shot_storage.drain().join().collect::<Vec<_>>();
+        let player = (&entities, &pos_storage, &player_storage)
+            .join()
+            .next()
+            .map(|(e, pos, p)| (e, pos.point, p.radius));
+        let player_pos = player.map(|(_, point, _)| point);
+        // на торе цели и препятствия проверяются и в соседних копиях мира
+        let boundary = settings.boundary;
+        let offsets = boundary.get_wrap_offsets();
+        let obstacles = (&pos_storage, &obstacle_storage)
+            .join()
+            .flat_map(|(pos, obstacle)| offsets.iter().map(move |o| (pos.point + o, obstacle)))
+            .collect::<Vec<_>>();
+        let ray_length = boundary.size.coords.norm();
+
+        let mut traces = Vec::new();
+        let mut ricochets = Vec::new();
+        let mut blasts = Vec::new();
+        for shot in shots.iter() {
+            let get_hit = |radius: f32, point: &Point| {
+                offsets
+                    .iter()
+                    .filter_map(|offset| get_hit_point(shot, radius, &(point + offset)))
+                    .min_by(|a, b| compare_vector_lengths(&(a - shot.from), &(b - shot.from)))
+            };
+            let enemies_hits = (&entities, &enemy_storage, &pos_storage)
+                .join()
+                .filter(|(e, _, _)| Some(*e) != shot.ignore)
+                .filter_map(|(e, enemy, pos)| {
+                    get_hit(enemy.radius, &pos.point).map(|hit| (e, hit))
+                });
+            let spawners_hits = (&entities, &spawner_storage, &pos_storage, &health_storage)
+                .join()
+                .filter_map(|(e, spawner, pos, _)| {
+                    get_hit(spawner.radius, &pos.point).map(|hit| (e, hit))
+                });
+
+            // выстрелы врагов не задевают других врагов и порталы, только игрока
+            let is_hostile = shot.is_hostile;
+            let mut hits = match (is_hostile, player) {
+                (true, Some((e, point, radius))) => get_hit(radius, &point)
+                    .map(|hit| (e, hit))
+                    .into_iter()
+                    .collect::<Vec<_>>(),
+                (true, None) => vec![],
+                (false, _) => enemies_hits.chain(spawners_hits).collect::<Vec<_>>(),
+            };
+            hits.sort_by(|(_, a), (_, b)| {
+                compare_vector_lengths(&(a - shot.from), &(b - shot.from))
+            });
+
+            // луч бесконечен, а снаряд проверяется только своим путём за тик
+            let ray_end = match shot.projectile {
+                Some(_) => shot.to,
+                None => {
+                    shot.from
+                        + (shot.to - shot.from)
+                            .try_normalize(0.001)
+                            .unwrap_or_else(Vector::zeros)
+                            * ray_length
+                }
+            };
+            // препятствие останавливает выстрел, цели за ним не задеваются
+            let wall = get_obstacle_hit(&shot.from, &ray_end, &obstacles);
+            let max_distance = wall.map_or(ray_length, |wall| (wall - shot.from).norm());
+            hits.retain(|(_, hit)| (hit - shot.from).norm() <= max_distance);
+
+            // пробивающий луч задевает несколько первых целей
+            let limit = match (is_hostile, shot.projectile) {
+                (false, None) => 1 + shot.pierce as usize,
+                _ => 1,
+            };
+            hits.truncate(limit);
+
+            if let (Some(projectile), false) = (shot.projectile, hits.is_empty() && wall.is_none())
+            {
+                entities.delete(projectile).unwrap();
+            }
+
+            for (target, hit_point) in hits.iter() {
+                if is_hostile {
+                    // пуля своего цвета поглощается и заряжает энергию
+                    let polarity = polarity_storage.get_mut(*target);
+                    let is_absorbed = shot.force != 0
+                        && polarity
+                            .as_ref()
+                            .is_some_and(|p| p.is_white == (shot.force > 0));
+                    if is_absorbed {
+                        if let Some(energy) = energy_storage.get_mut(*target) {
+                            energy.charge(ABSORB_ENERGY);
+                        }
+                        continue;
+                    }
+                    if is_protected(dash_storage.get(*target), status_storage.get(*target)) {
+                        continue;
+                    }
+                    apply_status(*target, shot.status, &mut status_storage);
+
+                    if let Some(health) = health_storage.get_mut(*target) {
+                        health.hp -= shot.force.abs();
+                    }
+                    if shot.recolor && shot.force != 0 {
+                        if let Some(polarity) = polarity {
+                            polarity.is_white = shot.force > 0;
+                        }
+                    }
+                    continue;
+                }
+
+                let multiplier = match (boss_storage.get(*target), pos_storage.get(*target)) {
+                    (Some(boss), Some(pos)) => {
+                        let hit_point = boundary.wrap(hit_point);
+                        get_weak_point_multiplier(
+                            boss, &pos.point, &hit_point, player_pos, &boundary,
+                        )
+                    }
+                    _ => 1,
+                };
+
+                damage_target(
+                    health_storage.get_mut(*target),
+                    color_storage.get_mut(*target),
+                    shot.force,
+                    multiplier,
+                );
+                apply_status(*target, shot.status, &mut status_storage);
+            }
+
+            // заряженный выстрел взрывается в точке попадания
+            let blast = hits
+                .last()
+                .filter(|_| !is_hostile && shot.blast_radius > 0.);
+            if let Some((_, hit_point)) = blast {
+                blasts.push((
+                    boundary.wrap(hit_point),
+                    Explosion::new(shot.blast_radius, shot.force, false),
+                ));
+            }
+
+            // рикошет уходит от последнего попадания к ближайшему другому врагу
+            let last_hit = hits.last().filter(|_| !is_hostile && shot.ricochet > 0);
+            if let Some((target, hit_point)) = last_hit {
+                let hit_point = boundary.wrap(hit_point);
+                let next = (&entities, &enemy_storage, &pos_storage)
+                    .join()
+                    .filter(|(e, _, _)| e != target)
+                    .map(|(_, _, pos)| boundary.get_offset(&hit_point, &pos.point))
+                    .filter(|offset| offset.norm() < RICOCHET_RANGE)
+                    .min_by(compare_vector_lengths);
+                if let Some(offset) = next {
+                    ricochets.push(Shot {
+                        from: hit_point,
+                        to: hit_point + offset,
+                        ricochet: shot.ricochet - 1,
+                        pierce: 0,
+                        ignore: Some(*target),
+                        ..*shot
+                    });
+                }
+            }
+
+            if shot.projectile.is_some() {
+                continue;
+            }
+
+            let to = match (hits.last(), wall) {
+                (Some((_, hit)), _) if hits.len() == limit => *hit,
+                (_, Some(wall)) if (wall - shot.from).norm() < (shot.to - shot.from).norm() => wall,
+                _ => shot.to,
+            };
+            for (from, to) in boundary.split_segment(&shot.from, &to) {
+                traces.push(ShotTrace {
+                    from,
+                    to,
+                    is_hostile,
+                    is_white: shot.force > 0,
+                });
+            }
+        }
+
+        for (point, explosion) in blasts {
+            create_explosion(
+                &entities,
+                point,
+                explosion,
+                &mut pos_storage,
+                &mut explosion_storage,
+                &mut ttl_storage,
+            );
+        }
+
+        // рикошет разбирается на следующем тике
+        for shot in ricochets {
+            entities
+                .build_entity()
+                .with(shot, &mut shot_storage)
+                .with(DeadByTtl { ttl: 1 }, &mut ttl_storage)
+                .build();
+        }
+
+        //DeadByTtl { ttl: 5 },
+        for trace in traces {
+            entities
+                .build_entity()
+                .with(trace, &mut trace_storage)
+                .with(DeadByTtl { ttl: 5 }, &mut ttl_storage)
+                .build();
+        }
+    }
+}
+
+const RICOCHET_RANGE: f32 = 8.;
+
+const EXPLOSION_TTL: i32 = 10;
+
+fn create_explosion(
+    entities: &specs::Entities,
+    point: Point,
+    explosion: Explosion,
+    pos_storage: &mut WriteStorage<Position>,
+    explosion_storage: &mut WriteStorage<Explosion>,
+    ttl_storage: &mut WriteStorage<DeadByTtl>,
+) {
+    entities
+        .build_entity()
+        .with(Position { point }, pos_storage)
+        .with(explosion, explosion_storage)
+        .with(DeadByTtl { ttl: EXPLOSION_TTL }, ttl_storage)
+        .build();
+}
+
+// все, чей круг задевает заданный круг
+fn query_circle<'a, I>(
+    center: &Point,
+    radius: f32,
+    candidates: I,
+    boundary: &Boundary,
+) -> Vec<Entity>
+where
+    I: Iterator<Item = (Entity, &'a Position, f32)>,
+{
+    candidates
+        .filter(|(_, pos, r)| boundary.get_offset(center, &pos.point).norm() < radius + r)
+        .map(|(e, _, _)| e)
+        .collect()
+}
+
+// бомба летит к точке броска и взрывается, когда догорит фитиль
+struct BombSystem;
+impl<'a> System<'a> for BombSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Bomb>,
+        WriteStorage<'a, BombThrower>,
+        ReadStorage<'a, Polarity>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Explosion>,
+        WriteStorage<'a, DeadByTtl>,
+        Write<'a, Input>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut bomb_storage,
+            mut thrower_storage,
+            polarity_storage,
+            mut pos_storage,
+            mut explosion_storage,
+            mut ttl_storage,
+            mut input,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let target = input.bomb_target.take();
+        let mut thrown = Vec::new();
+        for (thrower, polarity, pos) in
+            (&mut thrower_storage, polarity_storage.maybe(), &pos_storage).join()
+        {
+            if thrower.tick_to_throw > 0 {
+                thrower.tick_to_throw -= 1;
+                continue;
+            }
+
+            if let Some(target) = target {
+                thrower.tick_to_throw = BOMB_COOLDOWN_TICKS;
+                thrown.push((pos.point, target, polarity.map_or(1, Polarity::get_sign)));
+            }
+        }
+
+        for (point, target, sign) in thrown {
+            entities
+                .build_entity()
+                .with(Position { point }, &mut pos_storage)
+                .with(
+                    Bomb {
+                        target,
+                        fuse: BOMB_FUSE_TICKS,
+                        force: sign * BOMB_DAMAGE,
+                    },
+                    &mut bomb_storage,
+                )
+                .build();
+        }
+
+        let step = BOMB_SPEED / settings.fps as f32;
+        let mut detonated = Vec::new();
+        for (entity, bomb, pos) in (&entities, &mut bomb_storage, &mut pos_storage).join() {
+            let offset = settings.boundary.get_offset(&pos.point, &bomb.target);
+            pos.point += match offset.norm() > step {
+                true => offset.normalize() * step,
+                false => offset,
+            };
+
+            bomb.fuse -= 1;
+            if bomb.fuse <= 0 {
+                entities.delete(entity).unwrap();
+                detonated.push((pos.point, bomb.force));
+            }
+        }
+
+        for (point, force) in detonated {
+            create_explosion(
+                &entities,
+                point,
+                Explosion::new(BOMB_RADIUS, force, false),
+                &mut pos_storage,
+                &mut explosion_storage,
+                &mut ttl_storage,
+            );
+        }
+    }
+}
+
+const BOMB_COOLDOWN_TICKS: i32 = 100;
+const BOMB_FUSE_TICKS: i32 = 40;
+const BOMB_SPEED: f32 = 15.;
+const BOMB_RADIUS: f32 = 3.;
+const BOMB_DAMAGE: i32 = 3;
+
+// взрыв задевает всех в радиусе, а взрывы разных стихий, встретившись, дают реакцию
+struct ExplosionSystem;
+impl<'a> System<'a> for ExplosionSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Explosion>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, DeadByTtl>,
+        ReadStorage<'a, Enemy>,
+        ReadStorage<'a, Spawner>,
+        ReadStorage<'a, Player>,
+        ReadStorage<'a, Dash>,
+        ReadStorage<'a, StatusEffects>,
+        WriteStorage<'a, Health>,
+        WriteStorage<'a, Color>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut explosion_storage,
+            mut pos_storage,
+            mut ttl_storage,
+            enemy_storage,
+            spawner_storage,
+            player_storage,
+            dash_storage,
+            status_storage,
+            mut health_storage,
+            mut color_storage,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let mut reactions = Vec::new();
+        {
+            let mut alive = (&entities, &mut explosion_storage, &pos_storage)
+                .join()
+                .filter(|(_, e, _)| !e.is_reaction)
+                .collect::<Vec<_>>();
+            for i in 0..alive.len() {
+                for j in i + 1..alive.len() {
+                    let (a, b) = (&alive[i], &alive[j]);
+                    let is_reacting = !a.1.has_reacted
+                        && !b.1.has_reacted
+                        && a.1.is_white() != b.1.is_white()
+                        && has_circles_collision(&a.2.point, &b.2.point, a.1.radius + b.1.radius);
+                    if is_reacting {
+                        let point = na::center(&a.2.point, &b.2.point);
+                        let radius = a.1.radius + b.1.radius;
+                        let force = a.1.force.abs().max(b.1.force.abs());
+                        reactions.push((point, radius, force));
+                        alive[i].1.has_reacted = true;
+                        alive[j].1.has_reacted = true;
+                    }
+                }
+            }
+        }
+
+        for (point, radius, force) in reactions {
+            let mut explosion = Explosion::new(radius, force, false);
+            explosion.is_reaction = true;
+            create_explosion(
+                &entities,
+                point,
+                explosion,
+                &mut pos_storage,
+                &mut explosion_storage,
+                &mut ttl_storage,
+            );
+        }
+
+        for (explosion, pos) in (&mut explosion_storage, &pos_storage).join() {
+            if explosion.is_resolved {
+                continue;
+            }
+            explosion.is_resolved = true;
+
+            let enemies = (&entities, &pos_storage, &enemy_storage)
+                .join()
+                .map(|(e, pos, enemy)| (e, pos, enemy.radius));
+            let spawners = (&entities, &pos_storage, &spawner_storage)
+                .join()
+                .map(|(e, pos, spawner)| (e, pos, spawner.radius));
+            let targets = query_circle(
+                &pos.point,
+                explosion.radius,
+                enemies.chain(spawners),
+                &settings.boundary,
+            );
+            for target in targets {
+                let color = color_storage.get_mut(target);
+                // реакция сносит врагов обеих стихий
+                let force = match (&color, explosion.is_reaction) {
+                    (Some(color), true) if color.is_white => explosion.force.abs(),
+                    (Some(_), true) => -explosion.force.abs(),
+                    _ => explosion.force,
+                };
+                damage_target(health_storage.get_mut(target), color, force, 1);
+            }
+
+            if !explosion.is_hostile {
+                continue;
+            }
+
+            let players = (&entities, &pos_storage, &player_storage)
+                .join()
+                .map(|(e, pos, player)| (e, pos, player.radius));
+            for target in query_circle(&pos.point, explosion.radius, players, &settings.boundary) {
+                if is_protected(dash_storage.get(target), status_storage.get(target)) {
+                    continue;
+                }
+                if let Some(health) = health_storage.get_mut(target) {
+                    health.hp -= explosion.force.abs();
+                }
+            }
+        }
+    }
+}
+
+struct UltimateSystem;
+impl<'a> System<'a> for UltimateSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Ultimate>,
+        WriteStorage<'a, Energy>,
+        ReadStorage<'a, Polarity>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Explosion>,
+        WriteStorage<'a, DeadByTtl>,
+        Write<'a, Input>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut ultimate_storage,
+            mut energy_storage,
+            polarity_storage,
+            mut pos_storage,
+            mut explosion_storage,
+            mut ttl_storage,
+            mut input,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let is_used = input.use_ultimate;
+        input.use_ultimate = false;
+
+        let mut blasts = Vec::new();
+        for (ultimate, energy, polarity, pos) in (
+            &mut ultimate_storage,
+            &mut energy_storage,
+            &polarity_storage,
+            &pos_storage,
+        )
+            .join()
+        {
+            if ultimate.tick_to_ready > 0 {
+                ultimate.tick_to_ready -= 1;
+                continue;
+            }
+
+            if is_used && energy.is_full() {
+                energy.value = 0;
+                ultimate.tick_to_ready = ULTIMATE_COOLDOWN_TICKS;
+                let force = match polarity.is_white {
+                    true => ULTIMATE_DAMAGE,
+                    false => -ULTIMATE_DAMAGE,
+                };
+                blasts.push((pos.point, force));
+            }
+        }
+
+        for (point, force) in blasts {
+            create_explosion(
+                &entities,
+                point,
+                Explosion::new(ULTIMATE_RADIUS, force, false),
+                &mut pos_storage,
+                &mut explosion_storage,
+                &mut ttl_storage,
+            );
+        }
+    }
+}
+
+const ULTIMATE_COOLDOWN_TICKS: i32 = 500;
+const ULTIMATE_RADIUS: f32 = 15.;
+const ULTIMATE_DAMAGE: i32 = 5;
+
+struct StatusSystem;
+impl<'a> System<'a> for StatusSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, StatusEffects>,
+        WriteStorage<'a, Health>,
+        ReadStorage<'a, Dash>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, mut status_storage, mut health_storage, dash_storage): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        for (entity, statuses, mut health) in (
+            &entities,
+            &mut status_storage,
+            (&mut health_storage).maybe(),
+        )
+            .join()
+        {
+            let is_protected = is_protected(dash_storage.get(entity), Some(statuses));
+            for effect in statuses.active.iter_mut() {
+                effect.ticks -= 1;
+
+                // горение жжёт каждым стаком раз в интервал
+                if let (StatusKind::Burn, Some(health)) = (effect.kind, health.as_mut()) {
+                    if effect.ticks % BURN_INTERVAL == 0 && !is_protected {
+                        health.hp -= effect.stacks as i32;
+                    }
+                }
+            }
+            statuses.active.retain(|e| e.ticks > 0);
+        }
+    }
+}
+
+// погибая, враг может оставить усиление
+struct LootSystem;
+impl<'a> System<'a> for LootSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Loot>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Color>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Pickup>,
+        WriteStorage<'a, DeadByTtl>,
+        Write<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut loot_storage,
+            health_storage,
+            color_storage,
+            mut pos_storage,
+            mut pickup_storage,
+            mut ttl_storage,
+            mut settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let dying = (
+            &entities,
+            &loot_storage,
+            &color_storage,
+            health_storage.maybe(),
+            &pos_storage,
+        )
+            .join()
+            .filter(|(_, _, color, health, _)| is_dying(color, *health))
+            .map(|(e, _, _, _, pos)| (e, pos.point))
+            .collect::<Vec<_>>();
+
+        for (entity, point) in dying {
+            let loot = loot_storage.remove(entity).unwrap();
+            if settings.rnd.gen::<f32>() >= loot.chance {
+                continue;
+            }
+
+            let kinds = match loot.kinds.is_empty() {
+                true => &PICKUP_KINDS[..],
+                false => &loot.kinds[..],
+            };
+            let kind = kinds[settings.rnd.gen::<usize>() % kinds.len()];
+            entities
+                .build_entity()
+                .with(Position { point }, &mut pos_storage)
+                .with(
+                    Pickup {
+                        kind,
+                        radius: PICKUP_RADIUS,
+                    },
+                    &mut pickup_storage,
+                )
+                .with(DeadByTtl { ttl: PICKUP_TTL }, &mut ttl_storage)
+                .build();
+        }
+    }
+}
+
+struct PickupSystem;
+impl<'a> System<'a> for PickupSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        ReadStorage<'a, Pickup>,
+        ReadStorage<'a, Position>,
+        ReadStorage<'a, Player>,
+        WriteStorage<'a, PowerUps>,
+        WriteStorage<'a, StatusEffects>,
+        Read<'a, Settings>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            pickup_storage,
+            pos_storage,
+            player_storage,
+            mut power_up_storage,
+            mut status_storage,
+            settings,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        for (player_entity, power_ups, player, player_pos) in (
+            &entities,
+            &mut power_up_storage,
+            &player_storage,
+            &pos_storage,
+        )
+            .join()
+        {
+            for effect in power_ups.active.iter_mut() {
+                effect.1 -= 1;
+            }
+            power_ups.active.retain(|(_, ticks)| *ticks > 0);
+
+            for (entity, pickup, pos) in (&entities, &pickup_storage, &pos_storage).join() {
+                let is_touched = settings
+                    .boundary
+                    .get_offset(&pos.point, &player_pos.point)
+                    .norm()
+                    < pickup.radius + player.radius;
+                if !is_touched || !entities.is_alive(entity) {
+                    continue;
+                }
+
+                // скорость и щит - это обычные эффекты, остальное усиливает оружие
+                let status = match pickup.kind {
+                    PickupKind::Speed => Some(StatusKind::Haste),
+                    PickupKind::Shield => Some(StatusKind::Shield),
+                    PickupKind::RapidFire | PickupKind::Multishot => None,
+                };
+                match status {
+                    Some(kind) => apply_status(
+                        player_entity,
+                        Some((kind, POWER_UP_TICKS)),
+                        &mut status_storage,
+                    ),
+                    None => power_ups.grant(pickup.kind, POWER_UP_TICKS),
+                }
+                entities.delete(entity).unwrap();
+            }
+        }
+    }
+}
+
+// взрывоопасный враг, погибая, взрывается сам
+struct ExplosiveSystem;
+impl<'a> System<'a> for ExplosiveSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        WriteStorage<'a, Explosive>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Color>,
+        WriteStorage<'a, Position>,
+        WriteStorage<'a, Explosion>,
+        WriteStorage<'a, DeadByTtl>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            entities,
+            mut explosive_storage,
+            health_storage,
+            color_storage,
+            mut pos_storage,
+            mut explosion_storage,
+            mut ttl_storage,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let detonated = (
+            &entities,
+            &explosive_storage,
+            &color_storage,
+            health_storage.maybe(),
+            &pos_storage,
+        )
+            .join()
+            .filter(|(_, _, color, health, _)| is_dying(color, *health))
+            .map(|(e, explosive, color, _, pos)| {
+                let force = match color.is_white {
+                    true => explosive.config.damage,
+                    false => -explosive.config.damage,
+                };
+                (e, pos.point, explosive.config.radius, force)
+            })
+            .collect::<Vec<_>>();
+
+        for (entity, point, radius, force) in detonated {
+            explosive_storage.remove(entity);
+            create_explosion(
+                &entities,
+                point,
+                Explosion::new(radius, force, true),
+                &mut pos_storage,
+                &mut explosion_storage,
+                &mut ttl_storage,
+            );
+        }
+    }
+}
+
+fn damage_target(
+    health: Option<&mut Health>,
+    color: Option<&mut Color>,
+    force: i32,
+    multiplier: i32,
+) {
+    match (health, color) {
+        (Some(health), Some(color)) => {
+            if is_overcolored(color.is_white, force) {
+                health.hp -= force.abs() * multiplier;
+            }
+        }
+        (Some(health), None) => health.hp -= force.abs(),
+        (None, Some(color)) => color.damage = force,
+        (None, None) => {}
+    }
+}
+
+struct RemoveDestroyedSystem;
+impl<'a> System<'a> for RemoveDestroyedSystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Enemy>,
+        WriteStorage<'a, EnemyKillEvent>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, health_storage, enemy_storage, mut kill_event_storage): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let destroyed = (&entities, &health_storage)
+            .join()
+            .filter(|(_, h)| h.hp <= 0)
+            .map(|(e, _)| e)
+            .collect::<Vec<_>>();
+
+        for e in destroyed {
+            if enemy_storage.contains(e) {
+                entities
+                    .build_entity()
+                    .with(EnemyKillEvent {}, &mut kill_event_storage)
+                    .build();
+            }
+
+            entities.delete(e).unwrap();
+        }
+    }
+}
+
+struct BossSystem;
+impl<'a> System<'a> for BossSystem {
+    type SystemData = (
+        WriteStorage<'a, Boss>,
+        WriteStorage<'a, Enemy>,
+        WriteStorage<'a, Color>,
+        ReadStorage<'a, Health>,
+        ReadStorage<'a, Position>,
+        Write<'a, SpawnQueue>,
+    );
+
+    fn run(
+        &mut self,
+        (
+            mut boss_storage,
+            mut enemy_storage,
+            mut color_storage,
+            health_storage,
+            pos_storage,
+            mut spawn_queue,
+        ): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        for (boss, enemy, color, health, pos) in (
+            &mut boss_storage,
+            &mut enemy_storage,
+            &mut color_storage,
+            &health_storage,
+            &pos_storage,
+        )
+            .join()
+        {
+            let fraction = health.hp as f32 / health.max_hp.max(1) as f32;
+            let phase = boss
+                .phases
+                .iter()
+                .rposition(|p| fraction <= p.health_threshold)
+                .unwrap_or(0);
+
+            if phase > boss.phase {
+                boss.phase = phase;
+                boss.reset_timers();
+            }
+
+            let phase = &boss.phases[boss.phase];
+            color.is_white = phase.is_white;
+
+            if let Some(minions) = &phase.minions {
+                if boss.tick_to_minions > 0 {
+                    boss.tick_to_minions -= 1;
+                } else {
+                    let pattern = SpawnPattern::At {
+                        x: pos.point.x,
+                        y: pos.point.y,
+                    };
+                    spawn_queue.push(pattern, minions.count, minions.archetype.clone());
+                    boss.tick_to_minions = minions.interval;
+                }
+            }
+
+            let speed = phase.max_speed.unwrap_or(boss.base_speed);
+            enemy.max_speed = match phase.attack {
+                Some(BossAttack::Charge {
+                    speed_multiplier,
+                    duration,
+                    interval,
+                }) => {
+                    if boss.charge_ticks > 0 {
+                        boss.charge_ticks -= 1;
+                    } else if boss.tick_to_attack > 0 {
+                        boss.tick_to_attack -= 1;
+                    } else {
+                        boss.charge_ticks = duration;
+                        boss.tick_to_attack = interval;
+                    }
+
+                    match boss.charge_ticks {
+                        0 => speed,
+                        _ => speed * speed_multiplier,
+                    }
+                }
+                None => speed,
+            };
+        }
+    }
+}
+
+struct RemoveOvercoloredEmenySystem;
+impl<'a> System<'a> for RemoveOvercoloredEmenySystem {
+    type SystemData = (
+        specs::Entities<'a>,
+        ReadStorage<'a, Color>,
+        ReadStorage<'a, Enemy>,
+        WriteStorage<'a, EnemyKillEvent>,
+    );
+
+    fn run(
+        &mut self,
+        (entities, color_storage, enemy_storage, mut kill_event_storage): Self::SystemData,
+    ) {
+        use specs::Join;
+
+        let enemies = (&entities, &enemy_storage, &color_storage)
+            .join()
+            .filter(|(_, _, color)| is_overcolored(color.is_white, color.damage))
+            .map(|(entity, _, _)| entity)
+            .collect::<Vec<_>>();
+
+        for _ in 0..enemies.len() {
+            entities
+                .build_entity()
+                .with(EnemyKillEvent {}, &mut kill_event_storage)
+                .build();
+        }
+
+        for enemy in enemies {
+            entities.delete(enemy).unwrap();
+        }
+    }
+}
+
+// враг с запасом здоровья гибнет, когда оно кончится, остальные - от перекраски
+fn is_dying(color: &Color, health: Option<&Health>) -> bool {
+    match health {
+        Some(health) => health.hp <= 0,
+        None => is_overcolored(color.is_white, color.damage),
+    }
+}
+
+fn is_protected(dash: Option<&Dash>, statuses: Option<&StatusEffects>) -> bool {
+    dash.is_some_and(|d| d.is_invulnerable()) || statuses.is_some_and(|s| s.has(StatusKind::Shield))
+}
+
+fn apply_status(
+    entity: Entity,
+    status: Option<(StatusKind, i32)>,
+    status_storage: &mut WriteStorage<StatusEffects>,
+) {
+    if let Some((kind, ticks)) = status {
+        match status_storage.get_mut(entity) {
+            Some(statuses) => statuses.apply(kind, ticks),
+            None => {
+                let mut statuses = StatusEffects::default();
+                statuses.apply(kind, ticks);
+                status_storage.insert(entity, statuses).unwrap();
+            }
+        }
+    }
+}
+
+fn is_overcolored(is_white: bool, damage: i32) -> bool {
+    is_white && damage > 0 || !is_white && damage < 0
+}
+
+fn get_weak_point_multiplier(
+    boss: &Boss,
+    boss_pos: &Point,
+    hit: &Point,
+    player_pos: Option<Point>,
+    boundary: &Boundary,
+) -> i32 {
+    let (weak_point, player_pos) = match (boss.phases[boss.phase].weak_point, player_pos) {
+        (Some(weak_point), Some(player_pos)) => (weak_point, player_pos),
+        _ => return 1,
+    };
+
+    let to_player = boundary.get_offset(boss_pos, &player_pos);
+    let to_hit = boundary.get_offset(boss_pos, hit);
+    let angle = to_hit.y.atan2(to_hit.x) - to_player.y.atan2(to_player.x) - weak_point.angle;
+    let angle = angle.sin().atan2(angle.cos());
+
+    match angle.abs() <= weak_point.arc / 2. {
+        true => weak_point.damage_multiplier,
+        false => 1,
+    }
+}
+
+fn get_hit_point(shot: &Shot, radius: f32, center: &Point) -> Option<Point> {
+    let radius = radius + shot.radius;
+    if shot.projectile.is_some() {
+        // снаряд, начавший тик внутри цели, попадает сразу
+        if has_circles_collision(&shot.from, center, radius) {
+            return Some(shot.from);
+        }
+
+        let length = (shot.to - shot.from).norm();
+        if length < 0.001 {
+            return None;
+        }
+
+        return math::get_cross_points_with_sphere(center, radius, &shot.from, &shot.to)
+            .into_iter()
+            .filter(|p| (p - shot.from).norm() <= length)
+            .min_by(|a, b| compare_vector_lengths(&(a - shot.from), &(b - shot.from)));
+    }
+
+    let mut cross_points = math::get_cross_points_with_sphere(center, radius, &shot.from, &shot.to);
+
+    //из всех точек выбираем самую ближайшую
+    cross_points.sort_by(|a, b| compare_vector_lengths(&(a - shot.from), &(b - shot.from)));
+
+    match cross_points.len() {
+        0 => None,
+        _ => Some(cross_points[0]),
+    }
+}
+
+fn compare_vector_lengths(a: &Vector, &b: &Vector) -> Ordering {
+    let a_len = a.norm_squared();
+    let b_len = b.norm_squared();
+
+    a_len.partial_cmp(&b_len).unwrap()
+}
+
+fn has_circles_collision(a: &Point2<f32>, b: &Point2<f32>, minimum_distance: f32) -> bool {
+    let distance = na::distance_squared(a, b);
+    distance < minimum_distance * minimum_distance
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use specs::Join;
+
+    fn create_player(world: &mut specs::World, x: f32, y: f32) -> specs::EntityBuilder<'_> {
+        world
+            .create_entity()
+            .with(Player {
+                max_speed: 6.,
+                radius: 0.25,
+            })
+            .with(Position {
+                point: Point::new(x, y),
+            })
+    }
+
+    fn create_enemy(world: &mut specs::World, x: f32, y: f32) -> specs::EntityBuilder<'_> {
+        world
+            .create_entity()
+            .with(Enemy::new(&EnemyArchetype::default()))
+            .with(Position {
+                point: Point::new(x, y),
+            })
+    }
+
+    fn create_colored_enemy(
+        world: &mut specs::World,
+        x: f32,
+        y: f32,
+        is_white: bool,
+    ) -> specs::EntityBuilder<'_> {
+        create_enemy(world, x, y).with(Color {
+            is_white,
+            damage: 0,
+        })
+    }
+
+    #[test]
+    fn distance_squared_test() {
+        let distance = na::distance_squared(&Point2::new(0., 0.), &Point2::new(3., 4.));
+        assert_eq!(25., distance);
+    }
+
+    #[test]
+    fn normalize_test() {
+        let vector = Vector2::new(0.0, 0.0);
+        let vec2 = vector.try_normalize(0.01);
+
+        assert!(vec2.is_none());
+    }
+
+    #[test]
+    fn spawn_points_keep_safe_distance_test() {
+        let world_size = Point::new(50., 40.);
+        let player = Point::new(2., 2.);
+        let mut rnd = SmallRng::seed_from_u64(0);
+
+        let patterns = [
+            SpawnPattern::Edges,
+            SpawnPattern::Corners,
+            SpawnPattern::Ring { radius: 3. },
+            SpawnPattern::Line { length: 10. },
+            SpawnPattern::Cluster,
+        ];
+
+        for shape in [ArenaShape::Rectangle, ArenaShape::Circle] {
+            let boundary = Boundary::new(shape, world_size, None);
+            for pattern in patterns.iter() {
+                let points =
+                    get_spawn_points(*pattern, 8, 0.5, &boundary, Some(player), 8., &mut rnd);
+
+                for p in points {
+                    assert!(na::distance(&p, &player) >= 8.);
+                    assert!((boundary.confine(&p, 0.5) - p).norm() < 0.001);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn spawn_marker_activates_after_telegraph_test() {
+        let mut state = MainState::new();
+        state.init();
+        state.step();
+
+        assert_eq!(10, state.spec_world.read_storage::<SpawnMarker>().count());
+        assert_eq!(0, state.spec_world.read_storage::<Enemy>().count());
+
+        let telegraph_ticks = state
+            .spec_world
+            .read_resource::<Settings>()
+            .spawn_telegraph_ticks;
+        for _ in 0..=telegraph_ticks {
+            state.step();
+        }
+
+        assert_eq!(0, state.spec_world.read_storage::<SpawnMarker>().count());
+        assert_eq!(10, state.spec_world.read_storage::<Enemy>().count());
+    }
+
+    #[test]
+    fn wave_director_test() {
+        let level = Level::from_json(
+            r#"{
+                "waves": [
+                    {
+                        "trigger": { "type": "Tick", "tick": 0 },
+                        "groups": [{ "count": 3, "pattern": { "type": "Corners" } }]
+                    },
+                    {
+                        "trigger": { "type": "Delay", "ticks": 10 },
+                        "groups": [{ "count": 2, "pattern": { "type": "Edges" } }]
+                    }
+                ],
+                "win": { "type": "Survive", "ticks": 20 }
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        state.step();
+        state.step();
+        assert_eq!(3, state.spec_world.read_storage::<SpawnMarker>().count());
+
+        for _ in 0..10 {
+            state.step();
+        }
+        assert_eq!(5, state.spec_world.read_storage::<SpawnMarker>().count());
+
+        for _ in 0..10 {
+            state.step();
+        }
+        let director = state.spec_world.read_resource::<WaveDirector>();
+        assert_eq!(2, director.next_wave);
+        assert_eq!(LevelStatus::Won, director.status);
+    }
+
+    #[test]
+    fn spawner_respects_capacity_test() {
+        let level = Level::from_json(
+            r#"{
+                "spawners": [{ "x": 5, "y": 5, "interval": 0, "capacity": 2, "health": 3 }],
+                "win": { "type": "SpawnersDestroyed" }
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        for _ in 0..100 {
+            state.step();
+        }
+
+        assert_eq!(2, state.spec_world.read_storage::<SpawnedBy>().count());
+
+        let spawner = {
+            let entities = state.spec_world.entities();
+            let spawner_storage = state.spec_world.read_storage::<Spawner>();
+            (&entities, &spawner_storage).join().next().unwrap().0
+        };
+        state
+            .spec_world
+            .write_storage::<Health>()
+            .get_mut(spawner)
+            .unwrap()
+            .hp = 0;
+        state.step();
+        state.step();
+
+        assert_eq!(0, state.spec_world.read_storage::<Spawner>().count());
+        assert_eq!(
+            LevelStatus::Won,
+            state.spec_world.read_resource::<WaveDirector>().status
+        );
+    }
+
+    #[test]
+    fn shot_hits_spawner_once_test() {
+        let level = Level::from_json(
+            r#"{
+                "spawners": [{ "x": 5, "y": 5, "interval": 1000, "health": 10 }],
+                "win": { "type": "SpawnersDestroyed" }
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        state.set_shoot_force(1);
+        state.set_shoot_point(Some(Point::new(5., 5.)));
+        state.step();
+        state.set_shoot_point(None);
+        for _ in 0..5 {
+            state.step();
+        }
+
+        let health_storage = state.spec_world.read_storage::<Health>();
+        let spawner_storage = state.spec_world.read_storage::<Spawner>();
+        let (health, _) = (&health_storage, &spawner_storage).join().next().unwrap();
+        assert_eq!(9, health.hp);
+    }
+
+    #[test]
+    fn boss_phases_test() {
+        let level = Level::from_json(
+            r#"{
+                "archetypes": {
+                    "king": {
+                        "radius": 2,
+                        "health": 10,
+                        "boss": {
+                            "name": "King",
+                            "phases": [
+                                { "health_threshold": 1.0, "is_white": true },
+                                {
+                                    "health_threshold": 0.5,
+                                    "is_white": false,
+                                    "minions": { "count": 2, "interval": 1000 }
+                                }
+                            ]
+                        }
+                    }
+                },
+                "waves": [{
+                    "trigger": { "type": "Tick", "tick": 0 },
+                    "groups": [{ "archetype": "king", "count": 1, "pattern": { "type": "Corners" } }]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        let telegraph_ticks = state
+            .spec_world
+            .read_resource::<Settings>()
+            .spawn_telegraph_ticks;
+        for _ in 0..telegraph_ticks + 3 {
+            state.step();
+        }
+
+        let boss = {
+            let entities = state.spec_world.entities();
+            let boss_storage = state.spec_world.read_storage::<Boss>();
+            (&entities, &boss_storage).join().next().unwrap().0
+        };
+        assert!(
+            state
+                .spec_world
+                .read_storage::<Color>()
+                .get(boss)
+                .unwrap()
+                .is_white
+        );
+
+        state
+            .spec_world
+            .write_storage::<Health>()
+            .get_mut(boss)
+            .unwrap()
+            .hp = 4;
+        state.step();
+
+        assert_eq!(
+            1,
+            state
+                .spec_world
+                .read_storage::<Boss>()
+                .get(boss)
+                .unwrap()
+                .phase
+        );
+        assert!(
+            !state
+                .spec_world
+                .read_storage::<Color>()
+                .get(boss)
+                .unwrap()
+                .is_white
+        );
+        assert_eq!(2, state.spec_world.read_storage::<SpawnMarker>().count());
+    }
+
+    #[test]
+    fn enemies_slide_instead_of_freeze_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        world.register::<Position>();
+        world.register::<Velocity>();
+        world.register::<Enemy>();
+
+        let archetype = EnemyArchetype::default();
+        let moving = world
+            .create_entity()
+            .with(Enemy::new(&archetype))
+            .with(Position {
+                point: Point::new(0., 0.),
+            })
+            .with(Velocity {
+                velocity: Vector::new(0.1, 0.),
+            })
+            .build();
+        world
+            .create_entity()
+            .with(Enemy::new(&archetype))
+            .with(Position {
+                point: Point::new(1.05, 0.3),
+            })
+            .with(Velocity {
+                velocity: Vector::zeros(),
+            })
+            .build();
+
+        EnemiesPositionSystem.run_now(&world.res);
+
+        let pos = world.read_storage::<Position>().get(moving).unwrap().point;
+        assert!(pos.x > 0.);
+        assert!(pos.y < 0.);
+    }
+
+    #[test]
+    fn separation_steering_test() {
+        let world = specs::World::new();
+        let me = Neighbor {
+            entity: world.entities().create(),
+            point: Point::new(0., 0.),
+            velocity: Vector::zeros(),
+            radius: 0.5,
+            flock: None,
+        };
+        let neighbors = vec![Neighbor {
+            entity: world.entities().create(),
+            point: Point::new(0., 1.),
+            velocity: Vector::zeros(),
+            radius: 0.5,
+            flock: None,
+        }];
+
+        let direction = get_steering_direction(
+            &me,
+            &Point::new(10., 0.),
+            &Steering::default(),
+            &neighbors,
+            &[],
+        )
+        .unwrap();
+
+        assert!(direction.x > 0.);
+        assert!(direction.y < 0.);
+    }
+
+    #[test]
+    fn flock_cohesion_only_with_flockmates_test() {
+        let world = specs::World::new();
+        let neighbor = |point, flock| Neighbor {
+            entity: world.entities().create(),
+            point,
+            velocity: Vector::zeros(),
+            radius: 0.5,
+            flock,
+        };
+        let me = neighbor(Point::new(0., 0.), Some(1));
+        let steering = Steering {
+            cohesion: 1.,
+            ..Steering::default()
+        };
+        let target = Point::new(0., 10.);
+
+        let mates = vec![neighbor(Point::new(3., 0.), Some(1))];
+        let direction = get_steering_direction(&me, &target, &steering, &mates, &[]).unwrap();
+        assert!(direction.x > 0.);
+
+        let strangers = vec![neighbor(Point::new(3., 0.), Some(2))];
+        let direction = get_steering_direction(&me, &target, &steering, &strangers, &[]).unwrap();
+        assert_eq!(0., direction.x);
+    }
+
+    #[test]
+    fn formation_is_held_while_approaching_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut EnemiesVelocitySystem, &mut world.res);
+        System::setup(&mut EnemiesPositionSystem, &mut world.res);
+
+        create_player(&mut world, 45., 20.).build();
+
+        let def = FormationDef {
+            shape: FormationShape::Line,
+            spacing: 3.,
+            break_distance: 6.,
+        };
+        let starts = [
+            Point::new(5., 25.),
+            Point::new(3., 15.),
+            Point::new(6., 20.),
+        ];
+        let members = starts
+            .iter()
+            .enumerate()
+            .map(|(slot, point)| {
+                world
+                    .create_entity()
+                    .with(Enemy::new(&EnemyArchetype::default()))
+                    .with(Position { point: *point })
+                    .with(Velocity {
+                        velocity: Vector::zeros(),
+                    })
+                    .with(FormationMember {
+                        group: 1,
+                        slot,
+                        count: starts.len(),
+                        def,
+                    })
+                    .build()
+            })
+            .collect::<Vec<_>>();
+
+        for _ in 0..200 {
+            EnemiesVelocitySystem.run_now(&world.res);
+            EnemiesPositionSystem.run_now(&world.res);
+        }
+
+        // строй в линию поперёк направления на игрока
+        let positions = world.read_storage::<Position>();
+        let leader = positions.get(members[0]).unwrap().point;
+        let heading = (Point::new(45., 20.) - leader).normalize();
+        for (slot, member) in members.iter().enumerate().skip(1) {
+            let offset = positions.get(*member).unwrap().point - leader;
+            assert!((offset.norm() - 3. * slot as f32).abs() < 0.5);
+            assert!(offset.dot(&heading).abs() < 0.5);
+        }
+        assert!(na::distance(&leader, &Point::new(45., 20.)) > 6.);
+    }
+
+    #[test]
+    fn swarm_members_flock_with_default_steering_test() {
+        let mut state = MainState::new();
+        state.spec_world.write_resource::<SpawnQueue>().push(
+            SpawnPattern::Cluster,
+            3,
+            EnemyArchetype {
+                swarm: true,
+                ..EnemyArchetype::default()
+            },
+        );
+
+        let telegraph_ticks = state
+            .spec_world
+            .read_resource::<Settings>()
+            .spawn_telegraph_ticks;
+        for _ in 0..telegraph_ticks + 2 {
+            state.step();
+        }
+
+        let steering_storage = state.spec_world.read_storage::<Steering>();
+        let flock_storage = state.spec_world.read_storage::<Flock>();
+        assert_eq!(3, (&steering_storage, &flock_storage).join().count());
+        for (steering, _) in (&steering_storage, &flock_storage).join() {
+            assert!(steering.cohesion > 0.);
+        }
+    }
+
+    #[test]
+    fn ai_charge_is_telegraphed_test() {
+        use specs::RunNow;
+
+        let config = AiConfig {
+            charge: Some(ChargeConfig {
+                windup_ticks: 2,
+                dash_ticks: 2,
+                ..ChargeConfig::default()
+            }),
+            ..AiConfig::default()
+        };
+        let mut world = specs::World::new();
+        System::setup(&mut EnemyAiSystem, &mut world.res);
+        create_player(&mut world, 0., 0.).build();
+        let enemy = world
+            .create_entity()
+            .with(Position {
+                point: Point::new(3., 0.),
+            })
+            .with(Color {
+                is_white: true,
+                damage: 0,
+            })
+            .with(Ai::new(config))
+            .build();
+        let state = |world: &specs::World| world.read_storage::<Ai>().get(enemy).unwrap().state;
+
+        EnemyAiSystem.run_now(&world.res);
+        assert_eq!(AiState::ChargeWindup, state(&world));
+        assert!(world
+            .read_storage::<Ai>()
+            .get(enemy)
+            .unwrap()
+            .target
+            .is_none());
+
+        EnemyAiSystem.run_now(&world.res);
+        EnemyAiSystem.run_now(&world.res);
+        assert_eq!(AiState::Charge, state(&world));
+
+        EnemyAiSystem.run_now(&world.res);
+        EnemyAiSystem.run_now(&world.res);
+        assert_eq!(AiState::Chase, state(&world));
+    }
+
+    #[test]
+    fn ai_flees_from_matching_color_test() {
+        use specs::RunNow;
+
+        let config = AiConfig {
+            flee_matching_color: true,
+            ..AiConfig::default()
+        };
+        let mut world = specs::World::new();
+        System::setup(&mut EnemyAiSystem, &mut world.res);
+        create_player(&mut world, 0., 0.)
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
+            })
+            .build();
+        let enemy = world
+            .create_entity()
+            .with(Position {
+                point: Point::new(3., 0.),
+            })
+            .with(Color {
+                is_white: true,
+                damage: 0,
+            })
+            .with(Ai::new(config))
+            .build();
+
+        EnemyAiSystem.run_now(&world.res);
+        assert_eq!(
+            AiState::Flee,
+            world.read_storage::<Ai>().get(enemy).unwrap().state
+        );
+
+        for polarity in (&mut world.write_storage::<Polarity>()).join() {
+            polarity.is_white = false;
+        }
+        EnemyAiSystem.run_now(&world.res);
+        assert_eq!(
+            AiState::Chase,
+            world.read_storage::<Ai>().get(enemy).unwrap().state
+        );
+    }
+
+    #[test]
+    fn behavior_tree_shoots_through_trigger_test() {
+        let level = Level::from_json(
+            r#"{
+                "archetypes": {
+                    "gunner": {
+                        "gun": { "reload_ticks": 100 },
+                        "behavior": {
+                            "type": "Sequence",
+                            "children": [
+                                { "type": "Shoot" },
+                                { "type": "MoveTo", "target": { "type": "AwayFromPlayer" } }
+                            ]
+                        }
+                    }
+                },
+                "waves": [{
+                    "trigger": { "type": "Tick", "tick": 0 },
+                    "groups": [{ "archetype": "gunner", "count": 2, "pattern": { "type": "Corners" } }]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        let telegraph_ticks = state
+            .spec_world
+            .read_resource::<Settings>()
+            .spawn_telegraph_ticks;
+        for _ in 0..telegraph_ticks + 3 {
+            state.step();
+        }
+
+        assert_eq!(2, state.spec_world.read_storage::<ShotTrace>().count());
+        assert_eq!(2, state.spec_world.read_storage::<Enemy>().count());
+    }
+
+    #[test]
+    fn fast_projectile_does_not_tunnel_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ProjectileSystem, &mut world.res);
+        System::setup(&mut ShotSystem, &mut world.res);
+
+        let enemy = create_colored_enemy(&mut world, 5., 1., true).build();
+        // за тик снаряд пролетает больше диаметра врага
+        let projectile = world
+            .create_entity()
+            .with(Position {
+                point: Point::new(0., 1.),
+            })
+            .with(Projectile {
+                velocity: Vector::new(3., 0.),
+                radius: 0.2,
+                force: 1,
+                owner: None,
+                is_hostile: false,
+                recolor: false,
+                blast_radius: 0.,
+                status: None,
+            })
+            .build();
+
+        for _ in 0..2 {
+            ProjectileSystem.run_now(&world.res);
+            ShotSystem.run_now(&world.res);
+            world.maintain();
+        }
+
+        assert_eq!(1, world.read_storage::<Color>().get(enemy).unwrap().damage);
+        assert!(!world.is_alive(projectile));
+        assert_eq!(0, world.read_storage::<ShotTrace>().count());
+    }
+
+    #[test]
+    fn projectile_stays_hostile_after_owner_dies_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ProjectileSystem, &mut world.res);
+        System::setup(&mut ShotSystem, &mut world.res);
+
+        let player = create_player(&mut world, 2., 1.)
+            .with(Health { hp: 5, max_hp: 5 })
+            .build();
+        let owner = world
+            .create_entity()
+            .with(Enemy::new(&EnemyArchetype::default()))
+            .build();
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(0., 1.),
+            })
+            .with(Projectile {
+                velocity: Vector::new(3., 0.),
+                radius: 0.2,
+                force: -1,
+                owner: Some(owner),
+                is_hostile: true,
+                recolor: false,
+                blast_radius: 0.,
+                status: None,
+            })
+            .build();
+        world.delete_entity(owner).unwrap();
+        world.maintain();
+
+        ProjectileSystem.run_now(&world.res);
+        ShotSystem.run_now(&world.res);
+
+        assert_eq!(4, world.read_storage::<Health>().get(player).unwrap().hp);
+    }
+
+    #[test]
+    fn piercing_shot_hits_several_enemies_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ShotSystem, &mut world.res);
+
+        let enemies = (1..4)
+            .map(|i| create_colored_enemy(&mut world, 2. * i as f32, 0., true).build())
+            .collect::<Vec<_>>();
+        let owner = world.create_entity().build();
+
+        let config = GunConfig {
+            pierce: 1,
+            ..GunConfig::default()
+        };
+        for (shot, _) in get_gun_shots(
+            owner,
+            &config,
+            &Point::origin(),
+            &Point::new(1., 0.),
+            1,
+            false,
+        ) {
+            world.create_entity().with(shot).build();
+        }
+
+        ShotSystem.run_now(&world.res);
+
+        let color_storage = world.read_storage::<Color>();
+        let damages = enemies
+            .iter()
+            .map(|e| color_storage.get(*e).unwrap().damage)
+            .collect::<Vec<_>>();
+        assert_eq!(vec![1, 1, 0], damages);
+    }
+
+    #[test]
+    fn weapon_switch_keeps_reload_test() {
+        use specs::Join;
+
+        let mut state = MainState::new();
+        state.init();
+        let get_tick_to_reload = |state: &MainState| {
+            let gun_storage = state.spec_world.read_storage::<Gun>();
+            let player_storage = state.spec_world.read_storage::<Player>();
+            let (gun, _) = (&gun_storage, &player_storage).join().next().unwrap();
+            gun.tick_to_reload
+        };
+        {
+            let mut gun_storage = state.spec_world.write_storage::<Gun>();
+            let player_storage = state.spec_world.read_storage::<Player>();
+            let (gun, _) = (&mut gun_storage, &player_storage).join().next().unwrap();
+            gun.tick_to_reload = 4;
+        }
+
+        state.select_weapon(0);
+        assert_eq!(4, get_tick_to_reload(&state));
+
+        state.select_weapon(1);
+        assert_eq!(4, get_tick_to_reload(&state));
+    }
+
+    #[test]
+    fn holstered_weapon_keeps_heat_and_ammo_test() {
+        let weapons = get_default_weapons(5);
+        let mut gun = Gun::new(weapons[1].gun);
+        let mut arsenal = Arsenal::new(weapons);
+        arsenal.select(1, &mut gun);
+        gun.on_fired();
+        let ammo = gun.ammo;
+
+        arsenal.select(2, &mut gun);
+        gun.on_fired();
+        let heat = gun.heat;
+        assert!(heat > 0.);
+
+        arsenal.select(1, &mut gun);
+        assert_eq!(ammo, gun.ammo);
+        arsenal.select(2, &mut gun);
+        assert_eq!(heat, gun.heat);
+    }
+
+    #[test]
+    fn shotgun_spreads_pellets_symmetrically_test() {
+        let world = specs::World::new();
+        let owner = world.entities().create();
+        let config = GunConfig {
+            pellets: 3,
+            spread: 1.,
+            ..GunConfig::default()
+        };
+
+        let shots = get_gun_shots(
+            owner,
+            &config,
+            &Point::origin(),
+            &Point::new(1., 0.),
+            1,
+            false,
+        );
+
+        assert_eq!(3, shots.len());
+        assert!((shots[0].0.to.y + shots[2].0.to.y).abs() < 0.001);
+        assert!(shots[0].0.to.y < 0. && shots[1].0.to.y == 0.);
+        assert!((shots[2].0.to.y - 0.5f32.sin()).abs() < 0.001);
+    }
+
+    #[test]
+    fn charged_shot_pierces_when_fully_charged_test() {
+        use specs::RunNow;
+
+        let fire = |hold_ticks| {
+            let mut world = specs::World::new();
+            System::setup(&mut GunShotSystem, &mut world.res);
+            System::setup(&mut ShotSystem, &mut world.res);
+
+            world
+                .create_entity()
+                .with(Position {
+                    point: Point::origin(),
+                })
+                .with(Gun::new(GunConfig {
+                    charge_ticks: 10,
+                    charge_pierce: 2,
+                    ..GunConfig::default()
+                }))
+                .build();
+            let enemies = (1..4)
+                .map(|i| create_colored_enemy(&mut world, 2. * i as f32, 0., true).build())
+                .collect::<Vec<_>>();
+
+            world.write_resource::<Input>().shoot_point = Some(Point::new(10., 0.));
+            for _ in 0..hold_ticks {
+                GunShotSystem.run_now(&world.res);
+            }
+            world.write_resource::<Input>().shoot_point = None;
+            GunShotSystem.run_now(&world.res);
+            ShotSystem.run_now(&world.res);
+
+            let color_storage = world.read_storage::<Color>();
+            enemies
+                .iter()
+                .filter(|e| color_storage.get(**e).unwrap().damage != 0)
+                .count()
+        };
+
+        assert_eq!(1, fire(2));
+        assert_eq!(3, fire(10));
+    }
+
+    #[test]
+    fn released_charge_waits_for_reload_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut GunShotSystem, &mut world.res);
+
+        let mut gun = Gun::new(GunConfig {
+            charge_ticks: 10,
+            ..GunConfig::default()
+        });
+        gun.tick_to_reload = 20;
+        let shooter = world
+            .create_entity()
+            .with(Position {
+                point: Point::origin(),
+            })
+            .with(gun)
+            .build();
+
+        world.write_resource::<Input>().shoot_point = Some(Point::new(10., 0.));
+        for _ in 0..10 {
+            GunShotSystem.run_now(&world.res);
+        }
+        world.write_resource::<Input>().shoot_point = None;
+        GunShotSystem.run_now(&world.res);
+        world.maintain();
+        assert_eq!(0, world.read_storage::<Shot>().count());
+        assert_eq!(
+            1.,
+            world
+                .read_storage::<Gun>()
+                .get(shooter)
+                .unwrap()
+                .get_charge_level()
+        );
+
+        for _ in 0..10 {
+            GunShotSystem.run_now(&world.res);
+        }
+        world.maintain();
+        assert_eq!(1, world.read_storage::<Shot>().count());
+        assert_eq!(
+            0.,
+            world
+                .read_storage::<Gun>()
+                .get(shooter)
+                .unwrap()
+                .get_charge_level()
+        );
+    }
+
+    #[test]
+    fn magazine_reloads_without_trigger_test() {
+        let mut gun = Gun::new(GunConfig {
+            reload_ticks: 0,
+            magazine: Some(2),
+            magazine_reload_ticks: 3,
+            ..GunConfig::default()
+        });
+
+        for _ in 0..2 {
+            gun.update();
+            assert!(gun.is_ready());
+            gun.on_fired();
+        }
+        assert_eq!(Some(0), gun.ammo);
+        assert_eq!(Some(0.), gun.get_reload_progress());
+
+        // курок отпущен, а магазин всё равно перезаряжается
+        for _ in 0..2 {
+            gun.update();
+            assert!(!gun.is_ready());
+        }
+        gun.update();
+        assert!(gun.is_ready());
+        assert_eq!(Some(2), gun.ammo);
+    }
+
+    #[test]
+    fn gun_overheats_test() {
+        let mut gun = Gun::new(GunConfig {
+            reload_ticks: 0,
+            heat_per_shot: 0.5,
+            cooling: 0.,
+            overheat_ticks: 2,
+            ..GunConfig::default()
+        });
+
+        gun.on_fired();
+        assert!(gun.is_ready());
+        gun.on_fired();
+        assert!(gun.is_overheated() && !gun.is_ready());
+
+        gun.update();
+        gun.update();
+        assert!(gun.is_ready());
+    }
+
+    #[test]
+    fn ranged_enemy_damages_and_recolors_player_test() {
+        let level = Level::from_json(
+            r#"{
+                "archetypes": {
+                    "sniper": {
+                        "color": "Black",
+                        "max_speed": 0,
+                        "gun": { "reload_ticks": 10, "range": 100, "recolor_player": true }
+                    }
+                },
+                "waves": [{
+                    "trigger": { "type": "Tick", "tick": 0 },
+                    "groups": [{ "archetype": "sniper", "count": 1, "pattern": { "type": "Corners" } }]
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+
+        let telegraph_ticks = state
+            .spec_world
+            .read_resource::<Settings>()
+            .spawn_telegraph_ticks;
+        for _ in 0..telegraph_ticks + 3 {
+            state.step();
+        }
+
+        let health_storage = state.spec_world.read_storage::<Health>();
+        let player_storage = state.spec_world.read_storage::<Player>();
+        let (health, _) = (&health_storage, &player_storage).join().next().unwrap();
+        assert_eq!(health.max_hp - 1, health.hp);
+        let polarity_storage = state.spec_world.read_storage::<Polarity>();
+        let (polarity, _) = (&polarity_storage, &player_storage).join().next().unwrap();
+        assert!(!polarity.is_white);
+
+        let trace_storage = state.spec_world.read_storage::<ShotTrace>();
+        let trace = (&trace_storage).join().next().unwrap();
+        assert!(trace.is_hostile && !trace.is_white);
+    }
+
+    #[test]
+    fn blast_hurts_everyone_in_radius_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ExplosiveSystem, &mut world.res);
+        System::setup(&mut ExplosionSystem, &mut world.res);
+
+        let player = create_player(&mut world, 1., 0.)
+            .with(Health { hp: 5, max_hp: 5 })
+            .build();
+        let explosive = |world: &mut specs::World, x, hp| {
+            create_colored_enemy(world, x, 0., true)
+                .with(Health { hp, max_hp: 3 })
+                .with(Explosive {
+                    config: ExplosiveConfig::default(),
+                })
+                .build()
+        };
+        explosive(&mut world, 0., 0);
+        let near = explosive(&mut world, 2., 1);
+        let far = explosive(&mut world, 20., 1);
+
+        ExplosiveSystem.run_now(&world.res);
+        ExplosionSystem.run_now(&world.res);
+
+        let health_storage = world.read_storage::<Health>();
+        assert_eq!(4, health_storage.get(player).unwrap().hp);
+        assert_eq!(0, health_storage.get(near).unwrap().hp);
+        assert_eq!(1, health_storage.get(far).unwrap().hp);
+    }
+
+    #[test]
+    fn killed_explosive_enemy_blasts_in_chain_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ExplosiveSystem, &mut world.res);
+        System::setup(&mut ExplosionSystem, &mut world.res);
+
+        // каждый взрыв достаёт только до соседа, цепочка доходит до третьего
+        let explosive = |world: &mut specs::World, x, hp| {
+            create_colored_enemy(world, x, 0., true)
+                .with(Health { hp, max_hp: 3 })
+                .with(Explosive {
+                    config: ExplosiveConfig::default(),
+                })
+                .build()
+        };
+        explosive(&mut world, 0., 0);
+        explosive(&mut world, 2., 1);
+        explosive(&mut world, 4., 1);
+        let far = explosive(&mut world, 10., 1);
+
+        for _ in 0..3 {
+            ExplosiveSystem.run_now(&world.res);
+            ExplosionSystem.run_now(&world.res);
+        }
+
+        let pos_storage = world.read_storage::<Position>();
+        let explosion_storage = world.read_storage::<Explosion>();
+        let mut blasts = (&pos_storage, &explosion_storage)
+            .join()
+            .map(|(pos, _)| pos.point.x)
+            .collect::<Vec<_>>();
+        blasts.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        assert_eq!(vec![0., 2., 4.], blasts);
+
+        assert_eq!(1, world.read_storage::<Health>().get(far).unwrap().hp);
+        assert!(world.read_storage::<Explosive>().contains(far));
+    }
+
+    #[test]
+    fn dashing_player_survives_blast_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ExplosionSystem, &mut world.res);
+
+        let player = create_player(&mut world, 1., 0.)
+            .with(Health { hp: 5, max_hp: 5 })
+            .with(Dash {
+                tick_to_vulnerable: 10,
+                ..Dash::default()
+            })
+            .build();
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::origin(),
+            })
+            .with(Explosion::new(3., 2, true))
+            .build();
+
+        ExplosionSystem.run_now(&world.res);
+        assert_eq!(5, world.read_storage::<Health>().get(player).unwrap().hp);
+    }
+
+    #[test]
+    fn missiles_use_up_locks_in_order_test() {
+        let mut world = specs::World::new();
+        let first = world.create_entity().build();
+        let second = world.create_entity().build();
+        let mut lock = LockOn {
+            targets: vec![first, second],
+            ..LockOn::default()
+        };
+
+        assert_eq!(Some(first), lock.next_target());
+        assert_eq!(Some(second), lock.next_target());
+        assert_eq!(None, lock.next_target());
+    }
+
+    #[test]
+    fn missile_retargets_when_target_dies_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ProjectileSystem, &mut world.res);
+
+        let locked = create_enemy(&mut world, 20., 10.).build();
+        let other = create_enemy(&mut world, 10., 20.).build();
+        create_enemy(&mut world, 40., 40.).build();
+
+        let missile = world
+            .create_entity()
+            .with(Position {
+                point: Point::new(10., 10.),
+            })
+            .with(Projectile {
+                velocity: Vector::new(0.1, 0.),
+                radius: 0.2,
+                force: 1,
+                owner: None,
+                is_hostile: false,
+                recolor: false,
+                blast_radius: 0.,
+                status: None,
+            })
+            .with(HomingTarget {
+                target: Some(locked),
+                turn_rate: 1000.,
+            })
+            .build();
+
+        world.delete_entity(locked).unwrap();
+        world.maintain();
+        ProjectileSystem.run_now(&world.res);
+
+        let homing_storage = world.read_storage::<HomingTarget>();
+        assert_eq!(Some(other), homing_storage.get(missile).unwrap().target);
+
+        let projectile_storage = world.read_storage::<Projectile>();
+        let velocity = projectile_storage.get(missile).unwrap().velocity;
+        assert!(velocity.x.abs() < 0.001 && velocity.y > 0.);
+    }
+
+    #[test]
+    fn polarity_switch_has_cooldown_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut PolaritySystem, &mut world.res);
+        let player = world
+            .create_entity()
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
+            })
+            .build();
+
+        // повторная смена цвета сразу после первой не срабатывает
+        world.write_resource::<Input>().switch_polarity = true;
+        PolaritySystem.run_now(&world.res);
+        world.write_resource::<Input>().switch_polarity = true;
+        PolaritySystem.run_now(&world.res);
+        assert!(
+            !world
+                .read_storage::<Polarity>()
+                .get(player)
+                .unwrap()
+                .is_white
+        );
+    }
+
+    #[test]
+    fn player_absorbs_own_color_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut DamagePlayerSystem, &mut world.res);
+        System::setup(&mut RemoveOvercoloredEmenySystem, &mut world.res);
+        let player = create_player(&mut world, 5., 5.)
+            .with(Polarity {
+                is_white: false,
+                tick_to_switch: 0,
+            })
+            .with(Energy { value: 0, max: 10 })
+            .build();
+        let black = create_colored_enemy(&mut world, 5.5, 5., false).build();
+
+        // поглощённый враг погибает как убитый и засчитывается в очки
+        DamagePlayerSystem.run_now(&world.res);
+        DamagePlayerSystem.run_now(&world.res);
+        RemoveOvercoloredEmenySystem.run_now(&world.res);
+        world.maintain();
+
+        assert!(world.is_alive(player));
+        assert!(!world.is_alive(black));
+        assert_eq!(1, world.read_storage::<Energy>().get(player).unwrap().value);
+        assert_eq!(1, world.read_storage::<EnemyKillEvent>().count());
+    }
+
+    #[test]
+    fn own_color_touch_spares_boss_and_tough_enemies_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut DamagePlayerSystem, &mut world.res);
+        System::setup(&mut RemoveOvercoloredEmenySystem, &mut world.res);
+        let player = create_player(&mut world, 5., 5.)
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
+            })
+            .with(Energy { value: 0, max: 10 })
+            .build();
+        let tough = create_colored_enemy(&mut world, 5.5, 5., true)
+            .with(Health { hp: 3, max_hp: 3 })
+            .build();
+        let boss = create_colored_enemy(&mut world, 4.5, 5., true)
+            .with(Boss::new(
+                BossDef {
+                    name: "boss".to_string(),
+                    phases: vec![],
                 },
+                0.,
+            ))
+            .build();
+
+        DamagePlayerSystem.run_now(&world.res);
+        RemoveOvercoloredEmenySystem.run_now(&world.res);
+        world.maintain();
+
+        assert!(world.is_alive(player));
+        assert!(world.is_alive(tough));
+        assert!(world.is_alive(boss));
+        assert_eq!(0, world.read_storage::<Energy>().get(player).unwrap().value);
+    }
+
+    #[test]
+    fn other_color_kills_player_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut DamagePlayerSystem, &mut world.res);
+        let player = create_player(&mut world, 5., 5.)
+            .with(Polarity {
+                is_white: false,
+                tick_to_switch: 0,
+            })
+            .build();
+        create_colored_enemy(&mut world, 5.5, 5., true).build();
+
+        DamagePlayerSystem.run_now(&world.res);
+        world.maintain();
+
+        assert!(!world.is_alive(player));
+    }
+
+    #[test]
+    fn ultimate_clears_own_color_around_player_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut UltimateSystem, &mut world.res);
+        System::setup(&mut ExplosionSystem, &mut world.res);
+
+        let player = create_player(&mut world, 0., 0.)
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
             })
+            .with(Energy { value: 10, max: 10 })
+            .with(Ultimate::default())
+            .build();
+        let white = create_colored_enemy(&mut world, 5., 0., true).build();
+        let black = create_colored_enemy(&mut world, 5., 0., false).build();
+        let far = create_colored_enemy(&mut world, 50., 0., true).build();
+
+        world.write_resource::<Input>().use_ultimate = true;
+        UltimateSystem.run_now(&world.res);
+        ExplosionSystem.run_now(&world.res);
+
+        {
+            let color_storage = world.read_storage::<Color>();
+            let is_destroyed = |e| {
+                let color = color_storage.get(e).unwrap();
+                is_overcolored(color.is_white, color.damage)
+            };
+            assert!(is_destroyed(white));
+            assert!(!is_destroyed(black));
+            assert!(!is_destroyed(far));
+            assert_eq!(0, world.read_storage::<Energy>().get(player).unwrap().value);
         }
 
-        //DeadByTtl { ttl: 5 },
-        for trace in traces {
-            entities
-                .build_entity()
-                .with(trace, &mut trace_storage)
-                .with(DeadByTtl { ttl: 5 }, &mut ttl_storage)
-                .build();
+        // после применения нужна перезарядка, даже если энергия снова полна
+        world
+            .write_storage::<Energy>()
+            .get_mut(player)
+            .unwrap()
+            .value = 10;
+        world.write_resource::<Input>().use_ultimate = true;
+        UltimateSystem.run_now(&world.res);
+        assert_eq!(
+            10,
+            world.read_storage::<Energy>().get(player).unwrap().value
+        );
+    }
+
+    #[test]
+    fn dash_bursts_and_survives_contact_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut PlayerVelocitySystem, &mut world.res);
+        System::setup(&mut DamagePlayerSystem, &mut world.res);
+
+        let player = create_player(&mut world, 5., 5.)
+            .with(Velocity {
+                velocity: Vector::zeros(),
+            })
+            .with(Dash::default())
+            .build();
+        create_enemy(&mut world, 5.5, 5.).build();
+
+        let fps = world.read_resource::<Settings>().fps as f32;
+        world.write_resource::<Input>().player_direction = Vector::new(1., 0.);
+        world.write_resource::<Input>().dash = true;
+        PlayerVelocitySystem.run_now(&world.res);
+        DamagePlayerSystem.run_now(&world.res);
+        world.maintain();
+
+        let velocity = world
+            .read_storage::<Velocity>()
+            .get(player)
+            .unwrap()
+            .velocity;
+        assert_eq!(6. * DASH_SPEED_MULTIPLIER / fps, velocity.x);
+        assert!(world.is_alive(player));
+
+        // повторный рывок до перезарядки не срабатывает, после рывка игрок тормозит до обычной скорости
+        for _ in 0..DASH_TICKS + 20 {
+            world.write_resource::<Input>().dash = true;
+            PlayerVelocitySystem.run_now(&world.res);
         }
+        let velocity = world
+            .read_storage::<Velocity>()
+            .get(player)
+            .unwrap()
+            .velocity;
+        assert_eq!(6. / fps, velocity.x);
+
+        DamagePlayerSystem.run_now(&world.res);
+        world.maintain();
+        assert!(!world.is_alive(player));
     }
-}
 
-struct RemoveOvercoloredEmenySystem;
-impl<'a> System<'a> for RemoveOvercoloredEmenySystem {
-    type SystemData = (
-        specs::Entities<'a>,
-        ReadStorage<'a, Color>,
-        ReadStorage<'a, Enemy>,
-        WriteStorage<'a, EnemyKillEvent>,
-    );
+    #[test]
+    fn player_accelerates_and_ignores_dead_zone_test() {
+        let movement = MovementConfig {
+            max_speed: 5.,
+            acceleration: 40.,
+            deceleration: 100.,
+            response_curve: 2.,
+            dead_zone: 0.2,
+        };
 
-    fn run(
-        &mut self,
-        (entities, color_storage, enemy_storage, mut kill_event_storage): Self::SystemData,
-    ) {
-        use specs::Join;
+        let idle = get_player_target_velocity(&Vector::new(0.1, 0.1), 5., &movement);
+        assert_eq!(Vector::zeros(), idle);
+        let half = get_player_target_velocity(&Vector::new(0.6, 0.), 5., &movement);
+        assert!((half.x - 1.25).abs() < 0.001);
 
-        let enemies = (&entities, &enemy_storage, &color_storage)
-            .join()
-            .filter(|(_, _, color)| {
-                color.is_white && color.damage > 0 || color.is_white == false && color.damage < 0
+        let fps = 50;
+        let target = Vector::new(5., 0.) / fps as f32;
+        let mut velocity = Vector::zeros();
+        let mut ticks = 0;
+        while velocity != target {
+            velocity = get_inertial_velocity(&velocity, &target, &movement, fps);
+            ticks += 1;
+        }
+        assert_eq!(7, ticks);
+
+        let stopped = get_inertial_velocity(&velocity, &Vector::zeros(), &movement, fps);
+        assert!((stopped.x - 0.06).abs() < 0.001);
+    }
+
+    #[test]
+    fn dropped_pickup_grants_timed_power_up_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut LootSystem, &mut world.res);
+        System::setup(&mut PickupSystem, &mut world.res);
+        world.register::<Enemy>();
+
+        let player = create_player(&mut world, 5., 5.)
+            .with(PowerUps::default())
+            .build();
+        create_enemy(&mut world, 5.3, 5.)
+            .with(Color {
+                is_white: true,
+                damage: 1,
             })
-            .map(|(entity, _, _)| entity)
+            .with(Loot {
+                chance: 1.,
+                kinds: vec![PickupKind::RapidFire],
+            })
+            .build();
+
+        LootSystem.run_now(&world.res);
+        assert_eq!(1, world.read_storage::<Pickup>().join().count());
+
+        PickupSystem.run_now(&world.res);
+        world.maintain();
+        assert_eq!(0, world.read_storage::<Pickup>().join().count());
+
+        let power_ups = world.read_storage::<PowerUps>();
+        let power_ups = power_ups.get(player).unwrap();
+        assert!(power_ups.has(PickupKind::RapidFire));
+        assert_eq!(
+            vec![(PickupKind::RapidFire, POWER_UP_TICKS)],
+            power_ups.active
+        );
+    }
+
+    #[test]
+    fn shield_pickup_applies_status_effect_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut LootSystem, &mut world.res);
+        System::setup(&mut PickupSystem, &mut world.res);
+        world.register::<Enemy>();
+
+        let player = create_player(&mut world, 5., 5.)
+            .with(PowerUps::default())
+            .build();
+        create_enemy(&mut world, 5.3, 5.)
+            .with(Color {
+                is_white: true,
+                damage: 1,
+            })
+            .with(Loot {
+                chance: 1.,
+                kinds: vec![PickupKind::Shield],
+            })
+            .build();
+
+        LootSystem.run_now(&world.res);
+        PickupSystem.run_now(&world.res);
+        world.maintain();
+        assert_eq!(0, world.read_storage::<Pickup>().join().count());
+
+        // щит достаётся и игроку без эффектов, и не попадает в усиления оружия
+        let statuses = world.read_storage::<StatusEffects>();
+        assert!(statuses.get(player).unwrap().has(StatusKind::Shield));
+        let power_ups = world.read_storage::<PowerUps>();
+        assert!(power_ups.get(player).unwrap().active.is_empty());
+    }
+
+    #[test]
+    fn shield_and_dash_block_burn_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut StatusSystem, &mut world.res);
+
+        let burning = |kinds: &[StatusKind]| {
+            let mut statuses = StatusEffects::default();
+            for kind in kinds {
+                statuses.apply(*kind, 50);
+            }
+            statuses
+        };
+        let mut create_target = |statuses| {
+            world
+                .create_entity()
+                .with(Health { hp: 3, max_hp: 3 })
+                .with(statuses)
+                .build()
+        };
+        let open = create_target(burning(&[StatusKind::Burn]));
+        let shielded = create_target(burning(&[StatusKind::Burn, StatusKind::Shield]));
+        let dashing = create_target(burning(&[StatusKind::Burn]));
+        world
+            .write_storage::<Dash>()
+            .insert(
+                dashing,
+                Dash {
+                    tick_to_vulnerable: 50,
+                    ..Dash::default()
+                },
+            )
+            .unwrap();
+
+        for _ in 0..BURN_INTERVAL {
+            StatusSystem.run_now(&world.res);
+        }
+        let health_storage = world.read_storage::<Health>();
+        assert_eq!(2, health_storage.get(open).unwrap().hp);
+        assert_eq!(3, health_storage.get(shielded).unwrap().hp);
+        assert_eq!(3, health_storage.get(dashing).unwrap().hp);
+    }
+
+    #[test]
+    fn status_effects_stack_and_stop_enemies_test() {
+        use specs::RunNow;
+
+        let mut statuses = StatusEffects::default();
+        for _ in 0..5 {
+            statuses.apply(StatusKind::Slow, 10);
+        }
+        statuses.apply(StatusKind::Haste, 20);
+        statuses.apply(StatusKind::Haste, 5);
+        assert_eq!(STATUS_MAX_STACKS, statuses.get_stacks(StatusKind::Slow));
+        assert_eq!(1, statuses.get_stacks(StatusKind::Haste));
+        assert_eq!(0.25 * HASTE_MULTIPLIER, statuses.get_speed_multiplier());
+
+        let mut world = specs::World::new();
+        System::setup(&mut EnemiesVelocitySystem, &mut world.res);
+        System::setup(&mut StatusSystem, &mut world.res);
+
+        create_player(&mut world, 20., 20.).build();
+
+        let mut frozen = StatusEffects::default();
+        frozen.apply(StatusKind::Freeze, 2);
+        frozen.apply(StatusKind::Burn, 50);
+        let enemy = world
+            .create_entity()
+            .with(Enemy {
+                radius: 0.5,
+                max_speed: 4.,
+            })
+            .with(Position {
+                point: Point::new(5., 5.),
+            })
+            .with(Velocity {
+                velocity: Vector::zeros(),
+            })
+            .with(Health { hp: 3, max_hp: 3 })
+            .with(frozen)
+            .build();
+
+        EnemiesVelocitySystem.run_now(&world.res);
+        assert_eq!(
+            Vector::zeros(),
+            world
+                .read_storage::<Velocity>()
+                .get(enemy)
+                .unwrap()
+                .velocity
+        );
+
+        // заморозка проходит, а горение продолжает жечь
+        for _ in 0..BURN_INTERVAL {
+            StatusSystem.run_now(&world.res);
+        }
+        EnemiesVelocitySystem.run_now(&world.res);
+        assert!(
+            world
+                .read_storage::<Velocity>()
+                .get(enemy)
+                .unwrap()
+                .velocity
+                .norm()
+                > 0.
+        );
+        assert_eq!(2, world.read_storage::<Health>().get(enemy).unwrap().hp);
+    }
+
+    #[test]
+    fn allies_pick_targets_by_own_rule_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut AllySystem, &mut world.res);
+        System::setup(&mut GunShotSystem, &mut world.res);
+
+        create_player(&mut world, 10., 10.)
+            .with(Polarity {
+                is_white: true,
+                tick_to_switch: 0,
+            })
+            .build();
+        let drone = world
+            .create_entity()
+            .with(Ally {
+                kind: AllyKind::Drone,
+                rule: TargetRule::MatchingColor,
+                target: None,
+            })
+            .with(Orbit {
+                angle: 0.,
+                radius: DRONE_ORBIT_RADIUS,
+                angular_speed: DRONE_ANGULAR_SPEED,
+            })
+            .with(Position {
+                point: Point::origin(),
+            })
+            .with(Gun::new(get_drone_gun()))
+            .with(Trigger::default())
+            .build();
+
+        let target = |world: &mut specs::World, x, is_white, hp| {
+            create_colored_enemy(world, x, 10., is_white)
+                .with(Health { hp, max_hp: 3 })
+                .build()
+        };
+        target(&mut world, 12., false, 2);
+        let white = target(&mut world, 14., true, 3);
+        let weak = target(&mut world, 17., true, 1);
+
+        // лишняя турель вытесняет ту, которой меньше осталось жить
+        for x in [13., 14., 15.] {
+            world.write_resource::<Input>().turret_point = Some(Point::new(x, 12.));
+            AllySystem.run_now(&world.res);
+            world.maintain();
+        }
+        AllySystem.run_now(&world.res);
+
+        let allies = world.read_storage::<Ally>();
+        let positions = world.read_storage::<Position>();
+        let turrets = (&allies, &positions)
+            .join()
+            .filter(|(ally, _)| ally.kind == AllyKind::Turret)
+            .map(|(ally, pos)| (ally.target, pos.point.x))
             .collect::<Vec<_>>();
+        assert_eq!(MAX_TURRETS, turrets.len());
+        assert!(turrets.iter().all(|(target, _)| *target == Some(weak)));
+        assert!(turrets.iter().all(|(_, x)| *x > 13.));
+        assert_eq!(Some(white), allies.get(drone).unwrap().target);
+        let orbit = (positions.get(drone).unwrap().point - Point::new(10., 10.)).norm();
+        assert!((orbit - DRONE_ORBIT_RADIUS).abs() < 0.001);
+        drop((allies, positions));
 
-        for _ in 0..enemies.len() {
-            entities
-                .build_entity()
-                .with(EnemyKillEvent {}, &mut kill_event_storage)
-                .build();
+        // союзники стреляют цветом игрока
+        GunShotSystem.run_now(&world.res);
+        let shots = world.read_storage::<Shot>();
+        assert_eq!(3, shots.join().count());
+        assert!(shots.join().all(|shot| shot.force > 0));
+    }
+
+    #[test]
+    fn allies_hold_fire_without_player_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut AllySystem, &mut world.res);
+        System::setup(&mut GunShotSystem, &mut world.res);
+
+        let drone = world
+            .create_entity()
+            .with(Ally {
+                kind: AllyKind::Drone,
+                rule: TargetRule::Nearest,
+                target: None,
+            })
+            .with(Position {
+                point: Point::new(10., 10.),
+            })
+            .with(Gun::new(get_drone_gun()))
+            .with(Trigger {
+                target: Some(Point::new(12., 10.)),
+            })
+            .build();
+        create_colored_enemy(&mut world, 12., 10., true).build();
+
+        AllySystem.run_now(&world.res);
+        GunShotSystem.run_now(&world.res);
+
+        assert_eq!(
+            None,
+            world.read_storage::<Ally>().get(drone).unwrap().target
+        );
+        assert_eq!(0, world.read_storage::<Shot>().join().count());
+    }
+
+    #[test]
+    fn drones_come_only_from_level_test() {
+        let mut state = MainState::new();
+        state.init();
+        assert_eq!(0, state.spec_world.read_storage::<Ally>().join().count());
+
+        let level =
+            Level::from_json(r#"{ "drones": ["Nearest", "LowestHealth", "Nearest"] }"#).unwrap();
+        let mut state = MainState::new();
+        state.load_level(level);
+        state.init();
+        assert_eq!(3, state.spec_world.read_storage::<Ally>().join().count());
+    }
+
+    #[test]
+    fn obstacles_block_movement_and_shots_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ObstacleCollisionSystem, &mut world.res);
+        System::setup(&mut ShotSystem, &mut world.res);
+
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(10., 10.),
+            })
+            .with(Obstacle {
+                shape: Shape::Box {
+                    width: 4.,
+                    height: 2.,
+                },
+            })
+            .build();
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(20., 10.),
+            })
+            .with(Obstacle {
+                shape: Shape::Polygon {
+                    points: vec![(-1., -1.), (1., -1.), (0., 1.)],
+                },
+            })
+            .build();
+
+        let player = world
+            .create_entity()
+            .with(Player {
+                max_speed: 6.,
+                radius: 0.25,
+            })
+            .with(Position {
+                point: Point::new(10., 8.9),
+            })
+            .with(Velocity {
+                velocity: Vector::new(0.1, 0.1),
+            })
+            .build();
+        let stuck = world
+            .create_entity()
+            .with(Enemy::new(&EnemyArchetype::default()))
+            .with(Position {
+                point: Point::new(20., 9.8),
+            })
+            .build();
+        let hidden = world
+            .create_entity()
+            .with(Enemy::new(&EnemyArchetype::default()))
+            .with(Position {
+                point: Point::new(10., 14.),
+            })
+            .with(Color {
+                is_white: true,
+                damage: 0,
+            })
+            .build();
+
+        // игрок упирается в стену и скользит вдоль неё, застрявший враг выталкивается
+        ObstacleCollisionSystem.run_now(&world.res);
+        {
+            let pos_storage = world.read_storage::<Position>();
+            assert!((pos_storage.get(player).unwrap().point.y - 8.75).abs() < 0.001);
+            assert_eq!(
+                Vector::new(0.1, 0.),
+                world
+                    .read_storage::<Velocity>()
+                    .get(player)
+                    .unwrap()
+                    .velocity
+            );
+
+            let triangle = [
+                Point::new(19., 9.),
+                Point::new(21., 9.),
+                Point::new(20., 11.),
+            ];
+            let point = pos_storage.get(stuck).unwrap().point;
+            assert!(!math::is_point_in_polygon(&point, &triangle));
+            assert!(math::get_polygon_push_out(&point, 0.49, &triangle).is_none());
         }
 
-        for enemy in enemies {
-            entities.delete(enemy).unwrap();
+        let owner = world.create_entity().build();
+        let config = GunConfig::default();
+        let from = Point::new(10., 5.);
+        for (shot, _) in get_gun_shots(owner, &config, &from, &Point::new(10., 20.), 1, false) {
+            world.create_entity().with(shot).build();
         }
+        ShotSystem.run_now(&world.res);
+
+        assert_eq!(0, world.read_storage::<Color>().get(hidden).unwrap().damage);
+        let traces = world.read_storage::<ShotTrace>();
+        let trace = traces.join().next().unwrap();
+        assert!((trace.to - Point::new(10., 9.)).norm() < 0.001);
     }
-}
 
-fn get_enemy_hit_point(shot: &Shot, enemy: &Enemy, enemy_pos: &Point) -> Option<Point> {
-    let mut cross_points =
-        math::get_cross_points_with_sphere(&enemy_pos, enemy.radius, &shot.from, &shot.to);
+    #[test]
+    fn wrap_arena_blasts_and_pickups_reach_across_edges_test() {
+        use specs::RunNow;
 
-    //из всех точек выбираем самую ближайшую
-    cross_points.sort_by(|a, b| compare_vector_lengths(&(a - shot.from), &(b - shot.from)));
+        let mut world = specs::World::new();
+        System::setup(&mut ExplosionSystem, &mut world.res);
+        System::setup(&mut PickupSystem, &mut world.res);
+        world.write_resource::<Settings>().boundary =
+            Boundary::new(ArenaShape::Wrap, Point::new(20., 10.), None);
 
-    match cross_points.len() {
-        0 => None,
-        _ => Some(cross_points[0]),
+        let player = create_player(&mut world, 0.2, 5.)
+            .with(PowerUps::default())
+            .build();
+        let enemy = create_colored_enemy(&mut world, 0.5, 2., true)
+            .with(Health { hp: 3, max_hp: 3 })
+            .build();
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(19.5, 2.),
+            })
+            .with(Explosion::new(1., 2, false))
+            .build();
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(19.9, 5.),
+            })
+            .with(Pickup {
+                kind: PickupKind::RapidFire,
+                radius: PICKUP_RADIUS,
+            })
+            .build();
+
+        // взрыв и подбор у правого края достают до тех, кто у левого
+        ExplosionSystem.run_now(&world.res);
+        PickupSystem.run_now(&world.res);
+        assert_eq!(1, world.read_storage::<Health>().get(enemy).unwrap().hp);
+        assert!(world
+            .read_storage::<PowerUps>()
+            .get(player)
+            .unwrap()
+            .has(PickupKind::RapidFire));
     }
-}
 
-fn compare_vector_lengths(a: &Vector, &b: &Vector) -> Ordering {
-    let a_len = a.norm_squared();
-    let b_len = b.norm_squared();
+    #[test]
+    fn wrap_arena_carries_player_and_shots_across_edges_test() {
+        use specs::RunNow;
 
-    a_len.partial_cmp(&b_len).unwrap()
-}
+        let mut world = specs::World::new();
+        System::setup(&mut ConfineToArenaSystem, &mut world.res);
+        System::setup(&mut ShotSystem, &mut world.res);
+        let boundary = Boundary::new(ArenaShape::Wrap, Point::new(20., 10.), None);
+        world.write_resource::<Settings>().boundary = boundary;
 
-fn has_circles_collision(a: &Point2<f32>, b: &Point2<f32>, minimum_distance: f32) -> bool {
-    let distance = na::distance_squared(a, b);
-    distance < minimum_distance * minimum_distance
-}
+        let player = create_player(&mut world, 20.3, 5.).build();
+        let enemy = create_colored_enemy(&mut world, 1.5, 5., true).build();
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        ConfineToArenaSystem.run_now(&world.res);
+        let point = world.read_storage::<Position>().get(player).unwrap().point;
+        assert!((point - Point::new(0.3, 5.)).norm() < 0.001);
+        assert_eq!(
+            Vector::new(2., 0.),
+            boundary.get_offset(&Point::new(19., 5.), &Point::new(1., 5.))
+        );
+
+        // луч уходит за правый край и попадает во врага у левого
+        let owner = world.create_entity().build();
+        let from = Point::new(19., 5.);
+        for (shot, _) in get_gun_shots(
+            owner,
+            &GunConfig::default(),
+            &from,
+            &Point::new(20., 5.),
+            1,
+            false,
+        ) {
+            world.create_entity().with(shot).build();
+        }
+        ShotSystem.run_now(&world.res);
+
+        assert_eq!(1, world.read_storage::<Color>().get(enemy).unwrap().damage);
+        let traces = world.read_storage::<ShotTrace>();
+        let mut pieces = traces
+            .join()
+            .map(|t| (t.from.x, t.to.x))
+            .collect::<Vec<_>>();
+        pieces.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        assert_eq!(2, pieces.len());
+        assert!((pieces[0].0 - 0.).abs() < 0.001 && (pieces[0].1 - 1.).abs() < 0.001);
+        assert!((pieces[1].0 - 19.).abs() < 0.001 && (pieces[1].1 - 20.).abs() < 0.001);
+    }
 
     #[test]
-    fn distance_squared_test() {
-        let distance = na::distance_squared(&Point2::new(0., 0.), &Point2::new(3., 4.));
-        assert_eq!(25., distance);
+    fn shrinking_circle_arena_pushes_enemies_inside_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut ArenaSystem, &mut world.res);
+        System::setup(&mut ConfineToArenaSystem, &mut world.res);
+        world.write_resource::<Settings>().boundary = Boundary::new(
+            ArenaShape::Circle,
+            Point::new(20., 20.),
+            Some(ShrinkConfig {
+                delay_ticks: 1,
+                rate: 5.,
+                min_scale: 0.5,
+            }),
+        );
+
+        let enemy = create_enemy(&mut world, 19., 10.).build();
+
+        ArenaSystem.run_now(&world.res);
+        assert_eq!(1., world.read_resource::<Settings>().boundary.scale);
+        for _ in 0..10 {
+            ArenaSystem.run_now(&world.res);
+        }
+        assert_eq!(0.5, world.read_resource::<Settings>().boundary.scale);
+
+        ConfineToArenaSystem.run_now(&world.res);
+        let point = world.read_storage::<Position>().get(enemy).unwrap().point;
+        assert!(((point - Point::new(10., 10.)).norm() - 4.5).abs() < 0.001);
     }
 
     #[test]
-    fn normalize_test() {
-        let vector = Vector2::new(0.0, 0.0);
-        let vec2 = vector.try_normalize(0.01);
+    fn enemies_follow_flow_field_around_obstacles_test() {
+        use specs::RunNow;
 
-        assert!(vec2.is_none());
+        let mut world = specs::World::new();
+        System::setup(&mut FlowFieldSystem, &mut world.res);
+        System::setup(&mut EnemiesVelocitySystem, &mut world.res);
+        world.write_resource::<Settings>().boundary =
+            Boundary::new(ArenaShape::Rectangle, Point::new(30., 20.), None);
+
+        world
+            .create_entity()
+            .with(Position {
+                point: Point::new(15., 10.),
+            })
+            .with(Obstacle {
+                shape: Shape::Box {
+                    width: 2.,
+                    height: 12.,
+                },
+            })
+            .build();
+        let player = create_player(&mut world, 17.5, 5.5).build();
+        let enemy = world
+            .create_entity()
+            .with(Enemy {
+                radius: 0.5,
+                max_speed: 4.,
+            })
+            .with(Position {
+                point: Point::new(13.5, 12.5),
+            })
+            .with(Velocity {
+                velocity: Vector::zeros(),
+            })
+            .build();
+
+        let get_velocity = |world: &specs::World| {
+            world
+                .read_storage::<Velocity>()
+                .get(enemy)
+                .unwrap()
+                .velocity
+        };
+
+        FlowFieldSystem.run_now(&world.res);
+        while world.read_resource::<Pathfinding>().field.is_building() {
+            FlowFieldSystem.run_now(&world.res);
+        }
+        // игрок за стеной, короткий обход - вдоль неё через верхний край, а не к игроку наискось
+        EnemiesVelocitySystem.run_now(&world.res);
+        let velocity = get_velocity(&world);
+        assert!(velocity.x.abs() < 0.001);
+        assert!(velocity.y < 0.);
+
+        // игрок перешёл в другую клетку - волна пошла заново
+        world
+            .write_storage::<Position>()
+            .get_mut(player)
+            .unwrap()
+            .point = Point::new(20.5, 10.5);
+        FlowFieldSystem.run_now(&world.res);
+        let pathfinding = world.read_resource::<Pathfinding>();
+        assert_eq!(
+            pathfinding.field.get_cell(&Point::new(20.5, 10.5)),
+            pathfinding.field.target
+        );
+    }
+
+    #[test]
+    fn flow_field_finishes_while_player_runs_test() {
+        use specs::RunNow;
+
+        let mut world = specs::World::new();
+        System::setup(&mut FlowFieldSystem, &mut world.res);
+        world.write_resource::<Settings>().boundary =
+            Boundary::new(ArenaShape::Rectangle, Point::new(30., 20.), None);
+        let player = create_player(&mut world, 0.5, 10.5).build();
+
+        // игрок каждый тик в новой клетке, а волна всё равно достраивается
+        for x in 0..4 {
+            world
+                .write_storage::<Position>()
+                .get_mut(player)
+                .unwrap()
+                .point = Point::new(x as f32 + 0.5, 10.5);
+            FlowFieldSystem.run_now(&world.res);
+        }
+        let pathfinding = world.read_resource::<Pathfinding>();
+        let cell = pathfinding.field.get_cell(&Point::new(25.5, 10.5)).unwrap();
+        assert!(pathfinding.field.get_distance(cell).is_some());
     }
 }
@@ -1,4 +1,6 @@
 use crate::ecs;
+use crate::level;
+use crate::utils;
 use na::geometry::Point2;
 use serde_derive::{Deserialize, Serialize};
 use wasm_bindgen::prelude::*;
@@ -10,20 +12,42 @@ pub struct Game {
     is_shooting: bool,
 }
 
+impl Default for Game {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[wasm_bindgen]
 impl Game {
     #[wasm_bindgen(constructor)]
     pub fn new() -> Self {
+        utils::set_panic_hook();
+
         let mut state = ecs::MainState::new();
         state.init();
 
         Game {
-            state: state,
+            state,
             shoot_point: Point2::origin(),
             is_shooting: false,
         }
     }
 
+    #[wasm_bindgen]
+    pub fn load_level(&mut self, json: &str) -> Result<(), JsValue> {
+        let level = level::Level::from_json(json).map_err(|e| JsValue::from_str(&e.to_string()))?;
+
+        let mut state = ecs::MainState::new();
+        state.load_level(level);
+        state.init();
+
+        self.state = state;
+        self.update_game_shoot_point();
+
+        Ok(())
+    }
+
     #[wasm_bindgen]
     pub fn step(&mut self) {
         self.state.step();
@@ -48,7 +72,7 @@ impl Game {
 
     fn update_game_shoot_point(&mut self) {
         let shoot_point = match self.is_shooting {
-            true => Some(self.shoot_point.clone()),
+            true => Some(self.shoot_point),
             false => None,
         };
 
@@ -60,6 +84,41 @@ impl Game {
         self.state.set_shoot_force(force);
     }
 
+    #[wasm_bindgen]
+    pub fn reload(&mut self) {
+        self.state.reload();
+    }
+
+    #[wasm_bindgen]
+    pub fn dash(&mut self) {
+        self.state.dash();
+    }
+
+    #[wasm_bindgen]
+    pub fn use_ultimate(&mut self) {
+        self.state.use_ultimate();
+    }
+
+    #[wasm_bindgen]
+    pub fn switch_polarity(&mut self) {
+        self.state.switch_polarity();
+    }
+
+    #[wasm_bindgen]
+    pub fn throw_bomb(&mut self) {
+        self.state.throw_bomb(self.shoot_point);
+    }
+
+    #[wasm_bindgen]
+    pub fn place_turret(&mut self) {
+        self.state.place_turret(self.shoot_point);
+    }
+
+    #[wasm_bindgen]
+    pub fn select_weapon(&mut self, index: usize) {
+        self.state.select_weapon(index);
+    }
+
     #[wasm_bindgen]
     pub fn get_player_pos(&mut self) -> String {
         use specs::Join;
@@ -88,7 +147,30 @@ impl Game {
 
         let player_storage = self.state.spec_world.read_storage::<ecs::Player>();
         let pos_storage = self.state.spec_world.read_storage::<ecs::Position>();
-        let player = (&pos_storage, &player_storage).join().next();
+        let health_storage = self.state.spec_world.read_storage::<ecs::Health>();
+        let arsenal_storage = self.state.spec_world.read_storage::<ecs::Arsenal>();
+        let gun_storage = self.state.spec_world.read_storage::<ecs::Gun>();
+        let polarity_storage = self.state.spec_world.read_storage::<ecs::Polarity>();
+        let energy_storage = self.state.spec_world.read_storage::<ecs::Energy>();
+        let ultimate_storage = self.state.spec_world.read_storage::<ecs::Ultimate>();
+        let dash_storage = self.state.spec_world.read_storage::<ecs::Dash>();
+        let power_up_storage = self.state.spec_world.read_storage::<ecs::PowerUps>();
+        let status_storage = self.state.spec_world.read_storage::<ecs::StatusEffects>();
+        let player = (
+            &pos_storage,
+            &player_storage,
+            health_storage.maybe(),
+            arsenal_storage.maybe(),
+            gun_storage.maybe(),
+            polarity_storage.maybe(),
+            energy_storage.maybe(),
+            ultimate_storage.maybe(),
+            dash_storage.maybe(),
+            power_up_storage.maybe(),
+            status_storage.maybe(),
+        )
+            .join()
+            .next();
 
         let scope_storage = self.state.spec_world.read_storage::<ecs::Scope>();
         let scope = (&scope_storage).join().next();
@@ -96,13 +178,36 @@ impl Game {
         let enemy_storage = self.state.spec_world.read_storage::<ecs::Enemy>();
         let color_storage = self.state.spec_world.read_storage::<ecs::Color>();
 
-        let enemies = (&pos_storage, &enemy_storage, &color_storage)
+        let ai_storage = self.state.spec_world.read_storage::<ecs::Ai>();
+
+        let enemies = (
+            &pos_storage,
+            &enemy_storage,
+            &color_storage,
+            ai_storage.maybe(),
+            status_storage.maybe(),
+        )
             .join()
-            .map(|(pos, enemy, color)| Enemy {
+            .map(|(pos, enemy, color, ai, statuses)| Enemy {
                 x: pos.point.x,
                 y: pos.point.y,
                 radius: enemy.radius,
                 is_white: color.is_white,
+                state: ai.map(|ai| {
+                    match ai.state {
+                        ecs::AiState::Wander => "wander",
+                        ecs::AiState::Chase => "chase",
+                        ecs::AiState::Flank => "flank",
+                        ecs::AiState::ChargeWindup => "charge_windup",
+                        ecs::AiState::Charge => "charge",
+                        ecs::AiState::Retreat => "retreat",
+                        ecs::AiState::Flee => "flee",
+                    }
+                    .to_string()
+                }),
+                statuses: statuses.map_or(vec![], |s| {
+                    s.active.iter().map(|e| get_status_name(e.kind)).collect()
+                }),
             })
             .collect::<Vec<_>>();
 
@@ -114,15 +219,240 @@ impl Game {
                 from_y: decal.from.y,
                 to_x: decal.to.x,
                 to_y: decal.to.y,
+                is_hostile: decal.is_hostile,
+                is_white: decal.is_white,
             })
             .collect::<Vec<_>>();
 
+        let projectile_storage = self.state.spec_world.read_storage::<ecs::Projectile>();
+        let projectiles = (&pos_storage, &projectile_storage)
+            .join()
+            .map(|(pos, projectile)| Projectile {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: projectile.radius,
+                is_white: projectile.is_white(),
+                is_hostile: projectile.is_hostile,
+            })
+            .collect::<Vec<_>>();
+
+        let lock_storage = self.state.spec_world.read_storage::<ecs::LockOn>();
+        let mut locks = Vec::new();
+        for (lock, gun) in (&lock_storage, &gun_storage).join() {
+            let aimed = lock.aimed.filter(|e| !lock.targets.contains(e)).map(|e| {
+                (
+                    e,
+                    lock.aim_ticks as f32 / gun.config.lock_ticks.max(1) as f32,
+                )
+            });
+            let targets = lock.targets.iter().map(|e| (*e, 1.));
+            for (target, progress) in targets.chain(aimed) {
+                if let (Some(pos), Some(enemy)) =
+                    (pos_storage.get(target), enemy_storage.get(target))
+                {
+                    locks.push(Lock {
+                        x: pos.point.x,
+                        y: pos.point.y,
+                        radius: enemy.radius,
+                        progress: progress.min(1.),
+                    });
+                }
+            }
+        }
+
+        let explosion_storage = self.state.spec_world.read_storage::<ecs::Explosion>();
+        let explosions = (&pos_storage, &explosion_storage)
+            .join()
+            .map(|(pos, explosion)| Explosion {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: explosion.radius,
+                is_white: explosion.is_white(),
+                is_reaction: explosion.is_reaction,
+            })
+            .collect::<Vec<_>>();
+
+        let pickup_storage = self.state.spec_world.read_storage::<ecs::Pickup>();
+        let pickups = (&pos_storage, &pickup_storage)
+            .join()
+            .map(|(pos, pickup)| Pickup {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: pickup.radius,
+                kind: get_pickup_name(pickup.kind),
+            })
+            .collect::<Vec<_>>();
+
+        let obstacle_storage = self.state.spec_world.read_storage::<ecs::Obstacle>();
+        let obstacles = (&pos_storage, &obstacle_storage)
+            .join()
+            .map(|(pos, obstacle)| Obstacle {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: match obstacle.shape {
+                    ecs::Shape::Circle { radius } => Some(radius),
+                    _ => None,
+                },
+                points: obstacle
+                    .get_vertices(&pos.point)
+                    .iter()
+                    .map(|p| (p.x, p.y))
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+
+        let ally_storage = self.state.spec_world.read_storage::<ecs::Ally>();
+        let allies = (&pos_storage, &ally_storage)
+            .join()
+            .map(|(pos, ally)| Ally {
+                x: pos.point.x,
+                y: pos.point.y,
+                kind: match ally.kind {
+                    ecs::AllyKind::Drone => "drone",
+                    ecs::AllyKind::Turret => "turret",
+                }
+                .to_string(),
+                target: ally
+                    .target
+                    .and_then(|e| pos_storage.get(e))
+                    .map(|pos| (pos.point.x, pos.point.y)),
+            })
+            .collect::<Vec<_>>();
+
+        let bomb_storage = self.state.spec_world.read_storage::<ecs::Bomb>();
+        let bombs = (&pos_storage, &bomb_storage)
+            .join()
+            .map(|(pos, _)| Bomb {
+                x: pos.point.x,
+                y: pos.point.y,
+            })
+            .collect::<Vec<_>>();
+
+        let marker_storage = self.state.spec_world.read_storage::<ecs::SpawnMarker>();
+        let spawns = (&pos_storage, &marker_storage)
+            .join()
+            .map(|(pos, marker)| Spawn {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: marker.radius,
+                is_white: marker.is_white,
+                progress: 1.
+                    - marker.tick_to_activate as f32 / marker.telegraph_ticks.max(1) as f32,
+            })
+            .collect::<Vec<_>>();
+
+        let spawner_storage = self.state.spec_world.read_storage::<ecs::Spawner>();
+        let spawners = (&pos_storage, &spawner_storage, health_storage.maybe())
+            .join()
+            .map(|(pos, spawner, health)| Spawner {
+                x: pos.point.x,
+                y: pos.point.y,
+                radius: spawner.radius,
+                hp: health.map(|h| h.hp),
+                max_hp: health.map(|h| h.max_hp),
+            })
+            .collect::<Vec<_>>();
+
+        let boss_storage = self.state.spec_world.read_storage::<ecs::Boss>();
+        let boss = (&boss_storage, &health_storage, &color_storage)
+            .join()
+            .next()
+            .map(|(boss, health, color)| Boss {
+                name: boss.name.clone(),
+                hp: health.hp,
+                max_hp: health.max_hp,
+                phase: boss.phase,
+                phase_count: boss.phases.len(),
+                is_white: color.is_white,
+            });
+
+        let director = self.state.spec_world.read_resource::<ecs::WaveDirector>();
+        let level = director.level.as_ref().map(|l| Level {
+            name: l.name.clone(),
+            wave: director.next_wave,
+            wave_count: l.waves.len(),
+            status: match director.status {
+                ecs::LevelStatus::Running => "running".to_string(),
+                ecs::LevelStatus::Won => "won".to_string(),
+                ecs::LevelStatus::Lost => "lost".to_string(),
+            },
+        });
+
+        let boundary = self.state.get_boundary();
+        let (min, max) = (boundary.get_min(), boundary.get_max());
+        let arena = Arena {
+            shape: match boundary.shape {
+                ecs::ArenaShape::Rectangle => "rectangle",
+                ecs::ArenaShape::Circle => "circle",
+                ecs::ArenaShape::Wrap => "wrap",
+            }
+            .to_string(),
+            x: min.x,
+            y: min.y,
+            width: max.x - min.x,
+            height: max.y - min.y,
+            radius: boundary.get_radius(),
+        };
+
         let state = GameState {
             player: match player {
-                Some((pos, p)) => Some(Player {
+                Some((
+                    pos,
+                    p,
+                    health,
+                    arsenal,
+                    gun,
+                    polarity,
+                    energy,
+                    ultimate,
+                    dash,
+                    power_ups,
+                    statuses,
+                )) => Some(PlayerState {
                     x: pos.point.x,
                     y: pos.point.y,
                     radius: p.radius,
+                    hp: health.map_or(0, |h| h.hp),
+                    max_hp: health.map_or(0, |h| h.max_hp),
+                    is_white: polarity.is_none_or(|p| p.is_white),
+                    can_switch: polarity.is_some_and(|p| p.tick_to_switch <= 0),
+                    energy: energy.map_or(0, |e| e.value),
+                    max_energy: energy.map_or(0, |e| e.max),
+                    is_ultimate_ready: ultimate.is_some_and(|u| u.tick_to_ready <= 0)
+                        && energy.is_some_and(|e| e.is_full()),
+                    dash: dash.map(|dash| DashState {
+                        is_dashing: dash.is_dashing(),
+                        is_invulnerable: dash.is_invulnerable(),
+                        cooldown: dash.get_cooldown(),
+                    }),
+                    effects: power_ups.map_or(vec![], |p| {
+                        p.active
+                            .iter()
+                            .map(|(kind, ticks)| Effect {
+                                kind: get_pickup_name(*kind),
+                                ticks: *ticks,
+                            })
+                            .collect()
+                    }),
+                    statuses: statuses.map_or(vec![], |s| {
+                        s.active
+                            .iter()
+                            .map(|effect| Status {
+                                kind: get_status_name(effect.kind),
+                                ticks: effect.ticks,
+                                stacks: effect.stacks,
+                            })
+                            .collect()
+                    }),
+                    weapon: arsenal.map(|a| a.weapons[a.current].name.clone()),
+                    gun: gun.map(|gun| GunState {
+                        ammo: gun.ammo,
+                        magazine: gun.config.magazine,
+                        reload_progress: gun.get_reload_progress(),
+                        heat: gun.heat,
+                        is_overheated: gun.is_overheated(),
+                        charge: gun.get_charge_level(),
+                    }),
                 }),
                 _ => None,
             },
@@ -130,20 +460,67 @@ impl Game {
                 Some(s) => s.scope,
                 _ => 0,
             },
-            enemies: enemies,
-            shots: shots,
+            enemies,
+            spawns,
+            spawners,
+            shots,
+            projectiles,
+            explosions,
+            bombs,
+            locks,
+            pickups,
+            allies,
+            obstacles,
+            arena,
+            boss,
+            level,
         };
 
         serde_json::to_string(&state).unwrap()
     }
+
+    #[wasm_bindgen]
+    pub fn get_flow_field(&self) -> String {
+        let pathfinding = self.state.spec_world.read_resource::<ecs::Pathfinding>();
+        let field = &pathfinding.field;
+
+        let cells = 0..field.width * field.height;
+        let flow_field = FlowField {
+            x: field.origin.x,
+            y: field.origin.y,
+            cell_size: field.cell_size,
+            width: field.width,
+            height: field.height,
+            is_building: field.is_building(),
+            directions: cells
+                .clone()
+                .map(|cell| field.get_cell_direction(cell).map(|d| (d.x, d.y)))
+                .collect(),
+            distances: cells.map(|cell| field.get_distance(cell)).collect(),
+        };
+
+        serde_json::to_string(&flow_field).unwrap()
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 struct GameState {
-    player: Option<Player>,
+    player: Option<PlayerState>,
     enemies: Vec<Enemy>,
+    spawns: Vec<Spawn>,
+    spawners: Vec<Spawner>,
     shots: Vec<Shot>,
+    projectiles: Vec<Projectile>,
+    explosions: Vec<Explosion>,
+    bombs: Vec<Bomb>,
+    locks: Vec<Lock>,
+    pickups: Vec<Pickup>,
+    allies: Vec<Ally>,
+    obstacles: Vec<Obstacle>,
+    arena: Arena,
     scope: u32,
+    boss: Option<Boss>,
+    level: Option<Level>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -153,12 +530,81 @@ struct Player {
     radius: f32,
 }
 
+#[derive(Serialize, Deserialize)]
+struct PlayerState {
+    x: f32,
+    y: f32,
+    radius: f32,
+    hp: i32,
+    max_hp: i32,
+    is_white: bool,
+    can_switch: bool,
+    energy: i32,
+    max_energy: i32,
+    is_ultimate_ready: bool,
+    dash: Option<DashState>,
+    effects: Vec<Effect>,
+    statuses: Vec<Status>,
+    weapon: Option<String>,
+    gun: Option<GunState>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Effect {
+    kind: String,
+    ticks: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Status {
+    kind: String,
+    ticks: i32,
+    stacks: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct DashState {
+    is_dashing: bool,
+    is_invulnerable: bool,
+    cooldown: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct GunState {
+    ammo: Option<u32>,
+    magazine: Option<u32>,
+    reload_progress: Option<f32>,
+    heat: f32,
+    is_overheated: bool,
+    charge: f32,
+}
+
 #[derive(Serialize, Deserialize)]
 struct Enemy {
     x: f32,
     y: f32,
     radius: f32,
     is_white: bool,
+    state: Option<String>,
+    statuses: Vec<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Spawn {
+    x: f32,
+    y: f32,
+    radius: f32,
+    is_white: bool,
+    progress: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Spawner {
+    x: f32,
+    y: f32,
+    radius: f32,
+    hp: Option<i32>,
+    max_hp: Option<i32>,
 }
 
 #[derive(Serialize, Deserialize)]
@@ -167,4 +613,127 @@ struct Shot {
     from_y: f32,
     to_x: f32,
     to_y: f32,
+    is_hostile: bool,
+    is_white: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Projectile {
+    x: f32,
+    y: f32,
+    radius: f32,
+    is_white: bool,
+    is_hostile: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Explosion {
+    x: f32,
+    y: f32,
+    radius: f32,
+    is_white: bool,
+    is_reaction: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Bomb {
+    x: f32,
+    y: f32,
+}
+
+// текущие границы арены с учётом сжатия, круг вписан в прямоугольник
+#[derive(Serialize, Deserialize)]
+struct Arena {
+    shape: String,
+    x: f32,
+    y: f32,
+    width: f32,
+    height: f32,
+    radius: f32,
+}
+
+// у круга задан радиус, у прямоугольника и многоугольника - вершины
+#[derive(Serialize, Deserialize)]
+struct Obstacle {
+    x: f32,
+    y: f32,
+    radius: Option<f32>,
+    points: Vec<(f32, f32)>,
+}
+
+// клетки идут построчно, у закрытых и недостижимых нет ни направления, ни расстояния
+#[derive(Serialize, Deserialize)]
+struct FlowField {
+    x: f32,
+    y: f32,
+    cell_size: f32,
+    width: usize,
+    height: usize,
+    is_building: bool,
+    directions: Vec<Option<(f32, f32)>>,
+    distances: Vec<Option<f32>>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Ally {
+    x: f32,
+    y: f32,
+    kind: String,
+    target: Option<(f32, f32)>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Pickup {
+    x: f32,
+    y: f32,
+    radius: f32,
+    kind: String,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Lock {
+    x: f32,
+    y: f32,
+    radius: f32,
+    progress: f32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Boss {
+    name: String,
+    hp: i32,
+    max_hp: i32,
+    phase: usize,
+    phase_count: usize,
+    is_white: bool,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Level {
+    name: String,
+    wave: usize,
+    wave_count: usize,
+    status: String,
+}
+
+fn get_pickup_name(kind: ecs::PickupKind) -> String {
+    match kind {
+        ecs::PickupKind::RapidFire => "rapid_fire",
+        ecs::PickupKind::Speed => "speed",
+        ecs::PickupKind::Shield => "shield",
+        ecs::PickupKind::Multishot => "multishot",
+    }
+    .to_string()
+}
+
+fn get_status_name(kind: ecs::StatusKind) -> String {
+    match kind {
+        ecs::StatusKind::Slow => "slow",
+        ecs::StatusKind::Freeze => "freeze",
+        ecs::StatusKind::Stun => "stun",
+        ecs::StatusKind::Burn => "burn",
+        ecs::StatusKind::Haste => "haste",
+        ecs::StatusKind::Shield => "shield",
+    }
+    .to_string()
 }
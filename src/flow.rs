@@ -0,0 +1,255 @@
+use core::cmp::Ordering;
+use na::geometry::Point2;
+use na::Vector2;
+use std::collections::BinaryHeap;
+
+type Point = Point2<f32>;
+type Vector = Vector2<f32>;
+
+const STEPS: [(i32, i32); 8] = [
+    (1, 0),
+    (-1, 0),
+    (0, 1),
+    (0, -1),
+    (1, 1),
+    (1, -1),
+    (-1, 1),
+    (-1, -1),
+];
+
+// поле расстояний до цели по сетке, враг идёт в соседнюю клетку с меньшим расстоянием
+#[derive(Debug)]
+pub struct FlowField {
+    pub origin: Point,
+    pub cell_size: f32,
+    pub width: usize,
+    pub height: usize,
+    pub target: Option<usize>,
+    wrap: bool,
+    blocked: Vec<bool>,
+    // готовое поле, по нему ходят враги, пока волна строит следующее
+    distances: Vec<f32>,
+    pending: Vec<f32>,
+    queue: BinaryHeap<Node>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Node {
+    cost: f32,
+    cell: usize,
+}
+
+impl Eq for Node {}
+
+// куча в std максимальная, поэтому сравнение перевёрнуто
+impl Ord for Node {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .cost
+            .partial_cmp(&self.cost)
+            .unwrap_or(Ordering::Equal)
+            .then_with(|| self.cell.cmp(&other.cell))
+    }
+}
+
+impl PartialOrd for Node {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Default for FlowField {
+    fn default() -> Self {
+        FlowField::new(Point::origin(), 1., 0, 0, false)
+    }
+}
+
+impl FlowField {
+    pub fn new(origin: Point, cell_size: f32, width: usize, height: usize, wrap: bool) -> Self {
+        FlowField {
+            origin,
+            cell_size,
+            width,
+            height,
+            target: None,
+            wrap,
+            blocked: vec![false; width * height],
+            distances: vec![f32::INFINITY; width * height],
+            pending: vec![f32::INFINITY; width * height],
+            queue: BinaryHeap::new(),
+        }
+    }
+
+    pub fn get_cell(&self, point: &Point) -> Option<usize> {
+        let x = ((point.x - self.origin.x) / self.cell_size).floor() as i32;
+        let y = ((point.y - self.origin.y) / self.cell_size).floor() as i32;
+        self.get_cell_at(x, y)
+    }
+
+    pub fn get_cell_center(&self, cell: usize) -> Point {
+        let (x, y) = (cell % self.width, cell / self.width);
+        self.origin + Vector::new(x as f32 + 0.5, y as f32 + 0.5) * self.cell_size
+    }
+
+    pub fn is_building(&self) -> bool {
+        !self.queue.is_empty()
+    }
+
+    pub fn get_distance(&self, cell: usize) -> Option<f32> {
+        Some(self.distances[cell]).filter(|d| d.is_finite())
+    }
+
+    // новая волна начинается от цели, старое поле работает, пока она не дойдёт до конца
+    pub fn restart(&mut self, target: usize, blocked: Vec<bool>) {
+        self.blocked = blocked;
+        self.pending = vec![f32::INFINITY; self.width * self.height];
+        self.pending[target] = 0.;
+        self.queue.clear();
+        self.queue.push(Node {
+            cost: 0.,
+            cell: target,
+        });
+        self.target = Some(target);
+    }
+
+    // за тик разбирается не больше budget клеток
+    pub fn step(&mut self, budget: usize) {
+        if !self.is_building() {
+            return;
+        }
+
+        for _ in 0..budget {
+            let node = match self.queue.pop() {
+                Some(node) => node,
+                None => break,
+            };
+            if node.cost > self.pending[node.cell] {
+                continue;
+            }
+
+            for (next, cost) in self.get_neighbors(node.cell) {
+                let cost = node.cost + cost;
+                if self.blocked[next] || cost >= self.pending[next] {
+                    continue;
+                }
+                self.pending[next] = cost;
+                self.queue.push(Node { cost, cell: next });
+            }
+        }
+
+        if !self.is_building() {
+            self.distances = self.pending.clone();
+        }
+    }
+
+    pub fn get_direction(&self, point: &Point) -> Option<Vector> {
+        self.get_cell(point)
+            .and_then(|cell| self.get_cell_direction(cell))
+    }
+
+    // из закрытой клетки, куда враг мог заехать краем, тоже выводим к ближайшей открытой
+    pub fn get_cell_direction(&self, cell: usize) -> Option<Vector> {
+        let (x, y) = ((cell % self.width) as i32, (cell / self.width) as i32);
+        let (dx, dy, distance) = STEPS
+            .iter()
+            .filter(|(dx, dy)| self.can_step(x, y, *dx, *dy))
+            .filter_map(|(dx, dy)| {
+                self.get_cell_at(x + dx, y + dy)
+                    .map(|next| (*dx, *dy, self.distances[next]))
+            })
+            .min_by(|a, b| a.2.partial_cmp(&b.2).unwrap_or(Ordering::Equal))?;
+
+        match distance < self.distances[cell] {
+            true => Vector::new(dx as f32, dy as f32).try_normalize(0.001),
+            false => None,
+        }
+    }
+
+    fn get_cell_at(&self, x: i32, y: i32) -> Option<usize> {
+        let (width, height) = (self.width as i32, self.height as i32);
+        let (x, y) = match self.wrap {
+            true if width > 0 && height > 0 => (x.rem_euclid(width), y.rem_euclid(height)),
+            _ => (x, y),
+        };
+        match x >= 0 && y >= 0 && x < width && y < height {
+            true => Some((y * width + x) as usize),
+            false => None,
+        }
+    }
+
+    fn is_open(&self, x: i32, y: i32) -> bool {
+        self.get_cell_at(x, y)
+            .is_some_and(|cell| !self.blocked[cell])
+    }
+
+    // по диагонали нельзя срезать угол препятствия
+    fn can_step(&self, x: i32, y: i32, dx: i32, dy: i32) -> bool {
+        dx == 0 || dy == 0 || (self.is_open(x + dx, y) && self.is_open(x, y + dy))
+    }
+
+    fn get_neighbors(&self, cell: usize) -> Vec<(usize, f32)> {
+        let (x, y) = ((cell % self.width) as i32, (cell / self.width) as i32);
+        STEPS
+            .iter()
+            .filter(|(dx, dy)| self.can_step(x, y, *dx, *dy))
+            .filter_map(|(dx, dy)| {
+                let cost = match dx * dy {
+                    0 => 1.,
+                    _ => std::f32::consts::SQRT_2,
+                };
+                self.get_cell_at(x + dx, y + dy).map(|next| (next, cost))
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // стена поперёк поля с проходом внизу
+    fn get_field_with_wall() -> (FlowField, Vec<bool>) {
+        let field = FlowField::new(Point::origin(), 1., 10, 10, false);
+        let blocked = (0..100)
+            .map(|cell| cell % 10 == 5 && cell / 10 < 8)
+            .collect();
+        (field, blocked)
+    }
+
+    #[test]
+    fn field_leads_around_wall_test() {
+        let (mut field, blocked) = get_field_with_wall();
+        let target = field.get_cell(&Point::new(8.5, 1.5)).unwrap();
+        field.restart(target, blocked);
+        field.step(1000);
+
+        assert!(!field.is_building());
+        let direction = field.get_direction(&Point::new(2.5, 1.5)).unwrap();
+        assert!(direction.y > 0.);
+        assert_eq!(None, field.get_direction(&Point::new(8.5, 1.5)));
+
+        let start = field.get_cell(&Point::new(2.5, 1.5)).unwrap();
+        let around = field.get_distance(start).unwrap();
+        assert!(around > 2. * 6.);
+    }
+
+    #[test]
+    fn field_is_built_over_several_steps_test() {
+        let (mut field, blocked) = get_field_with_wall();
+        field.restart(0, blocked.clone());
+        field.step(1000);
+        let old_direction = field.get_direction(&Point::new(8.5, 1.5));
+
+        // пока новая волна не дошла до конца, враги идут по старому полю
+        field.restart(99, blocked);
+        field.step(10);
+        assert!(field.is_building());
+        assert_eq!(old_direction, field.get_direction(&Point::new(8.5, 1.5)));
+
+        while field.is_building() {
+            field.step(10);
+        }
+        let direction = field.get_direction(&Point::new(8.5, 1.5)).unwrap();
+        assert!(direction.y > 0.);
+    }
+}
@@ -0,0 +1,371 @@
+use crate::ecs::{
+    ArenaShape, EnemyArchetype, FormationDef, MovementConfig, Shape, ShrinkConfig, SpawnPattern,
+    TargetRule, WeaponDef,
+};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Level {
+    #[serde(default)]
+    pub name: String,
+    #[serde(default)]
+    pub arena: Arena,
+    #[serde(default)]
+    pub archetypes: HashMap<String, EnemyArchetype>,
+    #[serde(default)]
+    pub waves: Vec<Wave>,
+    #[serde(default)]
+    pub spawners: Vec<SpawnerDef>,
+    #[serde(default)]
+    pub win: WinCondition,
+    #[serde(default)]
+    pub weapons: Vec<WeaponDef>,
+    #[serde(default)]
+    pub movement: MovementConfig,
+    #[serde(default)]
+    pub obstacles: Vec<ObstacleDef>,
+    #[serde(default)]
+    pub drones: Vec<TargetRule>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Arena {
+    pub width: f32,
+    pub height: f32,
+    pub spawn_safe_distance: f32,
+    pub spawn_telegraph_ticks: i32,
+    pub shape: ArenaShape,
+    pub shrink: Option<ShrinkConfig>,
+}
+
+impl Default for Arena {
+    fn default() -> Self {
+        Arena {
+            width: 50.,
+            height: 40.,
+            spawn_safe_distance: 8.,
+            spawn_telegraph_ticks: 50,
+            shape: ArenaShape::Rectangle,
+            shrink: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Wave {
+    pub trigger: WaveTrigger,
+    pub groups: Vec<WaveGroup>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(tag = "type")]
+pub enum WaveTrigger {
+    Tick { tick: u32 },
+    Delay { ticks: u32 },
+    Scope { scope: u32 },
+    Cleared,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct WaveGroup {
+    #[serde(default)]
+    pub archetype: Option<String>,
+    pub count: u32,
+    pub pattern: SpawnPattern,
+    #[serde(default)]
+    pub formation: Option<FormationDef>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct SpawnerDef {
+    pub x: f32,
+    pub y: f32,
+    pub radius: f32,
+    pub interval: i32,
+    pub capacity: u32,
+    pub archetypes: Vec<ArchetypeWeight>,
+    pub health: Option<i32>,
+}
+
+impl Default for SpawnerDef {
+    fn default() -> Self {
+        SpawnerDef {
+            x: 0.,
+            y: 0.,
+            radius: 1.,
+            interval: 100,
+            capacity: 5,
+            archetypes: vec![],
+            health: None,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ObstacleDef {
+    pub x: f32,
+    pub y: f32,
+    pub shape: Shape,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ArchetypeWeight {
+    #[serde(default)]
+    pub archetype: Option<String>,
+    pub weight: u32,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default)]
+#[serde(tag = "type")]
+pub enum WinCondition {
+    #[default]
+    AllWavesCleared,
+    Scope {
+        scope: u32,
+    },
+    Survive {
+        ticks: u32,
+    },
+    SpawnersDestroyed,
+}
+
+#[derive(Debug)]
+pub enum LevelError {
+    Parse(serde_json::Error),
+    UnknownArchetype(String),
+    NoSpawnersToDestroy,
+    IndestructibleSpawner(usize),
+    BossWithoutHealth(String),
+    UnsortedBossPhases(String),
+}
+
+impl fmt::Display for LevelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LevelError::Parse(e) => write!(f, "invalid level: {}", e),
+            LevelError::UnknownArchetype(name) => write!(f, "unknown archetype '{}'", name),
+            LevelError::NoSpawnersToDestroy => {
+                write!(f, "level is won by destroying spawners but has none")
+            }
+            LevelError::IndestructibleSpawner(index) => {
+                write!(
+                    f,
+                    "spawner {} has no health and can not be destroyed",
+                    index
+                )
+            }
+            LevelError::BossWithoutHealth(name) => {
+                write!(f, "boss archetype '{}' has no health", name)
+            }
+            LevelError::UnsortedBossPhases(name) => write!(
+                f,
+                "boss archetype '{}' phase thresholds must go in descending order",
+                name
+            ),
+        }
+    }
+}
+
+impl Level {
+    pub fn from_json(json: &str) -> Result<Level, LevelError> {
+        let level: Level = serde_json::from_str(json).map_err(LevelError::Parse)?;
+
+        let groups = level
+            .waves
+            .iter()
+            .flat_map(|w| w.groups.iter())
+            .map(|g| &g.archetype);
+        let spawners = level
+            .spawners
+            .iter()
+            .flat_map(|s| s.archetypes.iter())
+            .map(|a| &a.archetype);
+
+        let unknown = groups
+            .chain(spawners)
+            .filter_map(|name| name.as_ref())
+            .find(|name| !level.archetypes.contains_key(*name));
+
+        if let Some(name) = unknown {
+            return Err(LevelError::UnknownArchetype(name.clone()));
+        }
+
+        // уровень на уничтожение спавнеров должен быть выигрываемым
+        if let WinCondition::SpawnersDestroyed = level.win {
+            if level.spawners.is_empty() {
+                return Err(LevelError::NoSpawnersToDestroy);
+            }
+            if let Some(index) = level.spawners.iter().position(|s| s.health.is_none()) {
+                return Err(LevelError::IndestructibleSpawner(index));
+            }
+        }
+
+        // фазы босса переключаются по здоровью, сверху вниз
+        for (name, archetype) in &level.archetypes {
+            let boss = match &archetype.boss {
+                Some(boss) => boss,
+                None => continue,
+            };
+            if archetype.health.is_none() {
+                return Err(LevelError::BossWithoutHealth(name.clone()));
+            }
+            let is_sorted = boss
+                .phases
+                .windows(2)
+                .all(|p| p[0].health_threshold >= p[1].health_threshold);
+            if !is_sorted {
+                return Err(LevelError::UnsortedBossPhases(name.clone()));
+            }
+        }
+
+        Ok(level)
+    }
+
+    pub fn get_archetype(&self, name: &Option<String>) -> EnemyArchetype {
+        name.as_ref()
+            .and_then(|name| self.archetypes.get(name))
+            .cloned()
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_level_test() {
+        let json = r#"{
+            "name": "first",
+            "arena": { "width": 30, "height": 20, "shape": "Circle", "shrink": { "rate": 0.05 } },
+            "archetypes": {
+                "runner": { "radius": 0.3, "max_speed": 7, "color": "White" }
+            },
+            "waves": [
+                {
+                    "trigger": { "type": "Tick", "tick": 0 },
+                    "groups": [
+                        { "count": 5, "pattern": { "type": "Edges" } },
+                        { "archetype": "runner", "count": 3, "pattern": { "type": "Ring", "radius": 10 } }
+                    ]
+                },
+                {
+                    "trigger": { "type": "Scope", "scope": 8 },
+                    "groups": [{
+                        "count": 4,
+                        "pattern": { "type": "Cluster" },
+                        "formation": { "shape": "V", "spacing": 2 }
+                    }]
+                }
+            ],
+            "win": { "type": "Survive", "ticks": 3000 },
+            "movement": { "acceleration": 30, "dead_zone": 0.2 },
+            "obstacles": [
+                { "x": 10, "y": 5, "shape": { "type": "Box", "width": 4, "height": 2 } },
+                { "x": 20, "y": 15, "shape": { "type": "Polygon", "points": [[0, 0], [2, 0], [1, 2]] } }
+            ],
+            "drones": ["Nearest", "MatchingColor"]
+        }"#;
+
+        let level = Level::from_json(json).unwrap();
+
+        assert_eq!(30., level.arena.width);
+        assert_eq!(50, level.arena.spawn_telegraph_ticks);
+        assert_eq!(ArenaShape::Circle, level.arena.shape);
+        assert_eq!(500, level.arena.shrink.unwrap().delay_ticks);
+        assert_eq!(2, level.waves.len());
+        assert_eq!(
+            7.,
+            level
+                .get_archetype(&level.waves[0].groups[1].archetype)
+                .max_speed
+        );
+        assert_eq!(2., level.waves[1].groups[0].formation.unwrap().spacing);
+        assert_eq!(30., level.movement.acceleration);
+        assert_eq!(6., level.movement.max_speed);
+        match &level.obstacles[1].shape {
+            Shape::Polygon { points } => assert_eq!(3, points.len()),
+            shape => panic!("unexpected obstacle shape {:?}", shape),
+        }
+        assert_eq!(
+            vec![TargetRule::Nearest, TargetRule::MatchingColor],
+            level.drones
+        );
+    }
+
+    #[test]
+    fn unknown_archetype_test() {
+        let json = r#"{
+            "waves": [{
+                "trigger": { "type": "Cleared" },
+                "groups": [{ "archetype": "ghost", "count": 1, "pattern": { "type": "Corners" } }]
+            }]
+        }"#;
+
+        match Level::from_json(json) {
+            Err(LevelError::UnknownArchetype(name)) => assert_eq!("ghost", name),
+            _ => panic!("level with unknown archetype should not load"),
+        }
+    }
+
+    #[test]
+    fn no_spawners_to_destroy_test() {
+        let json = r#"{ "win": { "type": "SpawnersDestroyed" } }"#;
+
+        match Level::from_json(json) {
+            Err(LevelError::NoSpawnersToDestroy) => {}
+            _ => panic!("level without spawners to destroy should not load"),
+        }
+    }
+
+    #[test]
+    fn indestructible_spawner_test() {
+        let json = r#"{
+            "spawners": [{ "x": 5, "y": 5, "health": 3 }, { "x": 10, "y": 5 }],
+            "win": { "type": "SpawnersDestroyed" }
+        }"#;
+
+        match Level::from_json(json) {
+            Err(LevelError::IndestructibleSpawner(index)) => assert_eq!(1, index),
+            _ => panic!("level with indestructible spawner should not load"),
+        }
+    }
+
+    #[test]
+    fn boss_without_health_test() {
+        let json = r#"{
+            "archetypes": {
+                "titan": { "boss": { "name": "Titan", "phases": [{ "health_threshold": 1 }] } }
+            }
+        }"#;
+
+        match Level::from_json(json) {
+            Err(LevelError::BossWithoutHealth(name)) => assert_eq!("titan", name),
+            _ => panic!("boss without health should not load"),
+        }
+    }
+
+    #[test]
+    fn unsorted_boss_phases_test() {
+        let json = r#"{
+            "archetypes": {
+                "titan": {
+                    "health": 50,
+                    "boss": {
+                        "name": "Titan",
+                        "phases": [{ "health_threshold": 0.5 }, { "health_threshold": 1 }]
+                    }
+                }
+            }
+        }"#;
+
+        match Level::from_json(json) {
+            Err(LevelError::UnsortedBossPhases(name)) => assert_eq!("titan", name),
+            _ => panic!("boss with unsorted phases should not load"),
+        }
+    }
+}
@@ -9,8 +9,11 @@ extern crate specs;
 #[macro_use]
 extern crate specs_derive;
 
+pub mod behavior;
 pub mod ecs;
 pub mod facade;
+pub mod flow;
+pub mod level;
 pub mod math;
 mod utils;
 
@@ -1,3 +1,4 @@
+use core::cmp::Ordering;
 use na::geometry::*;
 use na::Real;
 use na::Vector2;
@@ -25,6 +26,161 @@ pub fn get_cross_points_with_sphere(
         .collect::<Vec<Point>>()
 }
 
+pub fn rotate(vector: &Vector, angle: f32) -> Vector {
+    let (sin, cos) = angle.sin_cos();
+    Vector::new(
+        vector.x * cos - vector.y * sin,
+        vector.x * sin + vector.y * cos,
+    )
+}
+
+// поворачивает вектор к направлению, но не больше чем на заданный угол
+pub fn turn_towards(vector: &Vector, direction: &Vector, max_angle: f32) -> Vector {
+    let mut angle = direction.y.atan2(direction.x) - vector.y.atan2(vector.x);
+    if angle > f32::pi() {
+        angle -= f32::two_pi();
+    }
+    if angle < -f32::pi() {
+        angle += f32::two_pi();
+    }
+    rotate(vector, angle.max(-max_angle).min(max_angle))
+}
+
+fn cross(a: &Vector, b: &Vector) -> f32 {
+    a.x * b.y - a.y * b.x
+}
+
+pub fn get_segments_cross_point(
+    from: &Point,
+    to: &Point,
+    other_from: &Point,
+    other_to: &Point,
+) -> Option<Point> {
+    const EPS: f32 = 0.000001;
+
+    let ray = to - from;
+    let other_ray = other_to - other_from;
+    let denominator = cross(&ray, &other_ray);
+    // параллельные отрезки считаем непересекающимися
+    if Real::abs(denominator) < EPS {
+        return None;
+    }
+
+    let offset = other_from - from;
+    let t = cross(&offset, &other_ray) / denominator;
+    let u = cross(&offset, &ray) / denominator;
+    match (0. ..=1.).contains(&t) && (0. ..=1.).contains(&u) {
+        true => Some(from + ray * t),
+        false => None,
+    }
+}
+
+// первая точка входа отрезка в прямоугольник, отрезок изнутри упирается сразу
+pub fn get_segment_box_cross_point(
+    from: &Point,
+    to: &Point,
+    min: &Point,
+    max: &Point,
+) -> Option<Point> {
+    const EPS: f32 = 0.000001;
+
+    let ray = to - from;
+    let mut enter: f32 = 0.;
+    let mut exit: f32 = 1.;
+    for axis in 0..2 {
+        if Real::abs(ray[axis]) < EPS {
+            if from[axis] < min[axis] || from[axis] > max[axis] {
+                return None;
+            }
+            continue;
+        }
+
+        let near = (min[axis] - from[axis]) / ray[axis];
+        let far = (max[axis] - from[axis]) / ray[axis];
+        enter = enter.max(near.min(far));
+        exit = exit.min(near.max(far));
+        if enter > exit {
+            return None;
+        }
+    }
+
+    Some(from + ray * enter)
+}
+
+pub fn is_point_in_polygon(point: &Point, vertices: &[Point]) -> bool {
+    let mut is_inside = false;
+    for (i, a) in vertices.iter().enumerate() {
+        let b = &vertices[(i + 1) % vertices.len()];
+        if (a.y > point.y) != (b.y > point.y)
+            && point.x < a.x + (b.x - a.x) * (point.y - a.y) / (b.y - a.y)
+        {
+            is_inside = !is_inside;
+        }
+    }
+    is_inside
+}
+
+pub fn get_segment_polygon_cross_point(
+    from: &Point,
+    to: &Point,
+    vertices: &[Point],
+) -> Option<Point> {
+    if is_point_in_polygon(from, vertices) {
+        return Some(*from);
+    }
+
+    get_edges(vertices)
+        .filter_map(|(a, b)| get_segments_cross_point(from, to, a, b))
+        .min_by(|a, b| {
+            (a - from)
+                .norm_squared()
+                .partial_cmp(&(b - from).norm_squared())
+                .unwrap_or(Ordering::Equal)
+        })
+}
+
+pub fn get_closest_point_on_segment(point: &Point, from: &Point, to: &Point) -> Point {
+    let ray = to - from;
+    let length = ray.norm_squared();
+    if length < 0.000001 {
+        return *from;
+    }
+
+    let t = (point - from).dot(&ray) / length;
+    from + ray * t.clamp(0., 1.)
+}
+
+// на сколько сдвинуть круг, чтобы он перестал пересекать многоугольник
+pub fn get_polygon_push_out(center: &Point, radius: f32, vertices: &[Point]) -> Option<Vector> {
+    let closest = get_edges(vertices)
+        .map(|(a, b)| get_closest_point_on_segment(center, a, b))
+        .min_by(|a, b| {
+            (a - center)
+                .norm_squared()
+                .partial_cmp(&(b - center).norm_squared())
+                .unwrap_or(Ordering::Equal)
+        })?;
+
+    let offset = center - closest;
+    let distance = offset.norm();
+    match is_point_in_polygon(center, vertices) {
+        true => (-offset)
+            .try_normalize(0.000001)
+            .map(|normal| normal * (distance + radius)),
+        false if distance < radius => offset
+            .try_normalize(0.000001)
+            .map(|normal| normal * (radius - distance)),
+        false => None,
+    }
+}
+
+fn get_edges(vertices: &[Point]) -> impl Iterator<Item = (&Point, &Point)> {
+    vertices
+        .iter()
+        .enumerate()
+        .map(move |(i, a)| (a, &vertices[(i + 1) % vertices.len()]))
+}
+
 fn get_cross_points(radius: f32, from: &Point, to: &Point) -> Vec<Point> {
     const EPS: f32 = 0.000001;
 
@@ -49,5 +205,138 @@ fn get_cross_points(radius: f32, from: &Point, to: &Point) -> Vec<Point> {
 
     let point2 = Point::new(x0 - b * multiplicands, y0 + a * multiplicands);
 
-    return vec![point1, point2];
+    vec![point1, point2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_point(actual: Option<Point>, x: f32, y: f32) {
+        let point = actual.expect("ожидалась точка");
+        assert!((point.x - x).abs() < 1e-5, "{:?} != ({}, {})", point, x, y);
+        assert!((point.y - y).abs() < 1e-5, "{:?} != ({}, {})", point, x, y);
+    }
+
+    fn get_triangle() -> Vec<Point> {
+        vec![Point::new(0., 0.), Point::new(4., 0.), Point::new(2., 4.)]
+    }
+
+    #[test]
+    fn segments_cross_point_test() {
+        let cross = |a: (f32, f32), b: (f32, f32), c: (f32, f32), d: (f32, f32)| {
+            get_segments_cross_point(
+                &Point::new(a.0, a.1),
+                &Point::new(b.0, b.1),
+                &Point::new(c.0, c.1),
+                &Point::new(d.0, d.1),
+            )
+        };
+
+        assert_point(cross((0., 0.), (2., 2.), (0., 2.), (2., 0.)), 1., 1.);
+        // касание концами считается пересечением
+        assert_point(cross((0., 0.), (1., 1.), (1., 1.), (2., 0.)), 1., 1.);
+        // пересечение прямых за пределами отрезка
+        assert_eq!(cross((0., 0.), (1., 0.), (2., -1.), (2., 1.)), None);
+        // параллельные и лежащие на одной прямой
+        assert_eq!(cross((0., 0.), (2., 0.), (0., 1.), (2., 1.)), None);
+        assert_eq!(cross((0., 0.), (2., 0.), (1., 0.), (3., 0.)), None);
+        // вырожденный отрезок нулевой длины
+        assert_eq!(cross((1., 1.), (1., 1.), (0., 0.), (2., 2.)), None);
+    }
+
+    #[test]
+    fn segment_box_cross_point_test() {
+        let min = Point::new(1., 1.);
+        let max = Point::new(3., 2.);
+        let cross = |from: (f32, f32), to: (f32, f32)| {
+            get_segment_box_cross_point(
+                &Point::new(from.0, from.1),
+                &Point::new(to.0, to.1),
+                &min,
+                &max,
+            )
+        };
+
+        assert_point(cross((0., 1.5), (4., 1.5)), 1., 1.5);
+        // начало внутри коробки
+        assert_point(cross((2., 1.5), (5., 1.5)), 2., 1.5);
+        // скользит вдоль грани и через угол
+        assert_point(cross((0., 2.), (4., 2.)), 1., 2.);
+        assert_point(cross((0., 3.), (3., 0.)), 1., 2.);
+        // не долетает и проходит мимо параллельно грани
+        assert_eq!(cross((0., 1.5), (0.5, 1.5)), None);
+        assert_eq!(cross((0., 2.5), (4., 2.5)), None);
+        // вырожденный отрезок снаружи и внутри
+        assert_eq!(cross((0., 0.), (0., 0.)), None);
+        assert_point(cross((2., 1.5), (2., 1.5)), 2., 1.5);
+    }
+
+    #[test]
+    fn point_in_polygon_test() {
+        let triangle = get_triangle();
+        assert!(is_point_in_polygon(&Point::new(2., 1.), &triangle));
+        assert!(!is_point_in_polygon(&Point::new(5., 1.), &triangle));
+        assert!(!is_point_in_polygon(&Point::new(2., 5.), &triangle));
+        assert!(!is_point_in_polygon(&Point::new(-1., 0.), &triangle));
+
+        // невыпуклый уголок
+        let corner = vec![
+            Point::new(0., 0.),
+            Point::new(4., 0.),
+            Point::new(4., 4.),
+            Point::new(3., 4.),
+            Point::new(3., 1.),
+            Point::new(0., 1.),
+        ];
+        assert!(is_point_in_polygon(&Point::new(1., 0.5), &corner));
+        assert!(is_point_in_polygon(&Point::new(3.5, 3.), &corner));
+        assert!(!is_point_in_polygon(&Point::new(1., 3.), &corner));
+    }
+
+    #[test]
+    fn segment_polygon_cross_point_test() {
+        let triangle = get_triangle();
+        let cross = |from: (f32, f32), to: (f32, f32)| {
+            get_segment_polygon_cross_point(
+                &Point::new(from.0, from.1),
+                &Point::new(to.0, to.1),
+                &triangle,
+            )
+        };
+
+        assert_point(cross((2., -1.), (2., 2.)), 2., 0.);
+        // из двух пересекаемых граней берётся ближняя
+        assert_point(cross((-1., 1.), (5., 1.)), 0.5, 1.);
+        assert_point(cross((5., 1.), (-1., 1.)), 3.5, 1.);
+        // начало внутри многоугольника
+        assert_point(cross((2., 1.), (10., 1.)), 2., 1.);
+        // касание вершины
+        assert_point(cross((0., 4.), (4., 4.)), 2., 4.);
+        assert_eq!(cross((5., 5.), (6., 6.)), None);
+    }
+
+    #[test]
+    fn polygon_push_out_test() {
+        let square = vec![
+            Point::new(0., 0.),
+            Point::new(2., 0.),
+            Point::new(2., 2.),
+            Point::new(0., 2.),
+        ];
+        let push = |x: f32, y: f32| get_polygon_push_out(&Point::new(x, y), 0.5, &square);
+        let assert_push = |actual: Option<Vector>, x: f32, y: f32| {
+            assert_point(actual.map(Point::from), x, y);
+        };
+
+        // снаружи, но задевает край
+        assert_push(push(2.3, 1.), 0.2, 0.);
+        assert_push(push(1., -0.25), 0., -0.25);
+        // центр внутри выталкивается через ближайшую грань
+        assert_push(push(1.8, 1.), 0.7, 0.);
+        assert_push(push(1., 0.1), 0., -0.6);
+        // касание и промах
+        assert_eq!(push(2.5, 1.), None);
+        assert_eq!(push(5., 5.), None);
+    }
 }
@@ -30,12 +30,11 @@ if (isTouchDevice) {
             return;
         }
 
-        if (data.direction) {
-            let x = data.instance.frontPosition.x / 50;
-            let y = data.instance.frontPosition.y / 50;
+        // мёртвая зона и кривая отклика считаются в игре
+        let x = data.instance.frontPosition.x / 50;
+        let y = data.instance.frontPosition.y / 50;
 
-            game.set_player_direction(x, y);
-        }
+        game.set_player_direction(x, y);
     });
 
     const fireJoystick = nipplejs.create({
@@ -116,13 +115,30 @@ document.addEventListener("keyup", event => {
     game.set_player_direction(player_speed.x, player_speed.y);
 });
 
-let shoot_force = 1;
-game.set_shoot_force(shoot_force);
-
 document.addEventListener("keypress", event => {
     if (event.code == "Space") {
-        shoot_force *= -1;
-        game.set_shoot_force(shoot_force);
+        game.switch_polarity();
+    }
+});
+
+document.addEventListener("keydown", event => {
+    if (event.code.startsWith("Digit")) {
+        game.select_weapon(parseInt(event.code.substring(5)) - 1);
+    }
+    if (event.code == "KeyR") {
+        game.reload();
+    }
+    if (event.code == "KeyE") {
+        game.throw_bomb();
+    }
+    if (event.code == "KeyT") {
+        game.place_turret();
+    }
+    if (event.code == "KeyF") {
+        game.use_ultimate();
+    }
+    if (event.code == "ShiftLeft" || event.code == "ShiftRight") {
+        game.dash();
     }
 });
 
@@ -130,10 +146,47 @@ const ctx = canvas.getContext("2d");
 
 function draw(state) {
     ctx.clearRect(0, 0, canvas.width, canvas.height);
-    ctx.strokeRect(0, 0, 50 * CELL_SIZE, 40 * CELL_SIZE);
+    const arena = state.arena;
+    ctx.setLineDash(arena.shape == "wrap" ? [6, 6] : []);
+    if (arena.shape == "circle") {
+        ctx.beginPath();
+        ctx.arc((arena.x + arena.width / 2) * CELL_SIZE, (arena.y + arena.height / 2) * CELL_SIZE, arena.radius * CELL_SIZE, 0, 2 * Math.PI);
+        ctx.stroke();
+    } else {
+        ctx.strokeRect(arena.x * CELL_SIZE, arena.y * CELL_SIZE, arena.width * CELL_SIZE, arena.height * CELL_SIZE);
+    }
+    ctx.setLineDash([]);
 
     ctx.fillStyle = "#000";
     ctx.fillText(state.scope.toString(), 50, 50);
+    if (state.level != null) {
+        ctx.fillText(`wave ${state.level.wave}/${state.level.wave_count} ${state.level.status}`, 50, 65);
+    }
+
+    if (state.boss != null) {
+        const boss = state.boss;
+        const width = 300;
+
+        ctx.fillStyle = "#000";
+        ctx.fillText(`${boss.name} ${boss.phase + 1}/${boss.phase_count}`, 200, 50);
+        ctx.strokeRect(200, 55, width, 8);
+        ctx.fillStyle = boss.is_white ? "#ccc" : "#000";
+        ctx.fillRect(200, 55, (width * boss.hp) / boss.max_hp, 8);
+    }
+
+    ctx.fillStyle = "#876";
+    for (let i = 0; i < state.obstacles.length; ++i) {
+        const obstacle = state.obstacles[i];
+
+        ctx.beginPath();
+        if (obstacle.radius != null) {
+            ctx.arc(obstacle.x * CELL_SIZE, obstacle.y * CELL_SIZE, CELL_SIZE * obstacle.radius, 0, 2 * Math.PI);
+        } else {
+            obstacle.points.forEach(([x, y]) => ctx.lineTo(x * CELL_SIZE, y * CELL_SIZE));
+            ctx.closePath();
+        }
+        ctx.fill();
+    }
 
     ctx.strokeStyle = "#000";
 
@@ -149,29 +202,213 @@ function draw(state) {
         ctx.arc(enemy.x * CELL_SIZE, enemy.y * CELL_SIZE, CELL_SIZE * enemy.radius, 0, 2 * Math.PI);
         ctx.fill();
         ctx.stroke();
+
+        if (enemy.statuses.length > 0) {
+            ctx.strokeStyle = enemy.statuses.includes("burn") ? "#f80" : "#08f";
+            ctx.beginPath();
+            ctx.arc(enemy.x * CELL_SIZE, enemy.y * CELL_SIZE, CELL_SIZE * enemy.radius + 2, 0, 2 * Math.PI);
+            ctx.stroke();
+            ctx.strokeStyle = "#000";
+        }
+
+        if (enemy.state == "charge_windup") {
+            ctx.strokeStyle = "#f00";
+            ctx.beginPath();
+            ctx.arc(enemy.x * CELL_SIZE, enemy.y * CELL_SIZE, CELL_SIZE * enemy.radius + 3, 0, 2 * Math.PI);
+            ctx.stroke();
+            ctx.strokeStyle = "#000";
+        }
+    }
+
+    for (let i = 0; i < state.spawners.length; ++i) {
+        const spawner = state.spawners[i];
+        const size = spawner.radius * CELL_SIZE;
+
+        ctx.strokeStyle = "#808";
+        ctx.strokeRect(spawner.x * CELL_SIZE - size, spawner.y * CELL_SIZE - size, 2 * size, 2 * size);
+        if (spawner.hp != null) {
+            ctx.fillStyle = "#808";
+            ctx.fillRect(spawner.x * CELL_SIZE - size, spawner.y * CELL_SIZE + size + 2, (2 * size * spawner.hp) / spawner.max_hp, 3);
+        }
+    }
+
+    ctx.setLineDash([3, 3]);
+    for (let i = 0; i < state.spawns.length; ++i) {
+        const spawn = state.spawns[i];
+
+        ctx.strokeStyle = spawn.is_white ? "#aaa" : "#000";
+        ctx.beginPath();
+        ctx.arc(spawn.x * CELL_SIZE, spawn.y * CELL_SIZE, CELL_SIZE * spawn.radius * spawn.progress, 0, 2 * Math.PI);
+        ctx.stroke();
     }
+    ctx.setLineDash([]);
 
-    ctx.strokeStyle = "#aaa";
     for (let i = 0; i < state.shots.length; ++i) {
         const shot = state.shots[i];
+        ctx.strokeStyle = "#aaa";
+        if (shot.is_hostile) {
+            ctx.strokeStyle = shot.is_white ? "#f88" : "#800";
+        }
         ctx.beginPath();
         ctx.moveTo(shot.from_x * CELL_SIZE, shot.from_y * CELL_SIZE);
         ctx.lineTo(shot.to_x * CELL_SIZE, shot.to_y * CELL_SIZE);
         ctx.stroke();
     }
 
-    ctx.strokeStyle = "#000";
+    for (let i = 0; i < state.projectiles.length; ++i) {
+        const projectile = state.projectiles[i];
 
-    if (shoot_force > 0) ctx.fillStyle = "#fff";
-    else ctx.fillStyle = "#000";
+        ctx.strokeStyle = projectile.is_hostile ? "#800" : "#000";
+        ctx.fillStyle = projectile.is_white ? "#fff" : "#000";
+        ctx.beginPath();
+        ctx.arc(projectile.x * CELL_SIZE, projectile.y * CELL_SIZE, CELL_SIZE * projectile.radius, 0, 2 * Math.PI);
+        ctx.fill();
+        ctx.stroke();
+    }
+
+    const PICKUP_COLORS = { rapid_fire: "#f80", speed: "#0a0", shield: "#08f", multishot: "#a0a" };
+    for (let i = 0; i < state.pickups.length; ++i) {
+        const pickup = state.pickups[i];
+
+        ctx.fillStyle = PICKUP_COLORS[pickup.kind];
+        ctx.beginPath();
+        ctx.arc(pickup.x * CELL_SIZE, pickup.y * CELL_SIZE, CELL_SIZE * pickup.radius, 0, 2 * Math.PI);
+        ctx.fill();
+    }
+
+    for (let i = 0; i < state.allies.length; ++i) {
+        const ally = state.allies[i];
+        const size = CELL_SIZE * (ally.kind == "turret" ? 0.4 : 0.2);
+
+        if (ally.target != null) {
+            ctx.strokeStyle = "#0a0";
+            ctx.setLineDash([2, 4]);
+            ctx.beginPath();
+            ctx.moveTo(ally.x * CELL_SIZE, ally.y * CELL_SIZE);
+            ctx.lineTo(ally.target[0] * CELL_SIZE, ally.target[1] * CELL_SIZE);
+            ctx.stroke();
+            ctx.setLineDash([]);
+        }
+        ctx.fillStyle = "#0a0";
+        ctx.fillRect(ally.x * CELL_SIZE - size, ally.y * CELL_SIZE - size, 2 * size, 2 * size);
+    }
+
+    for (let i = 0; i < state.bombs.length; ++i) {
+        const bomb = state.bombs[i];
+
+        ctx.fillStyle = "#f80";
+        ctx.beginPath();
+        ctx.arc(bomb.x * CELL_SIZE, bomb.y * CELL_SIZE, CELL_SIZE * 0.25, 0, 2 * Math.PI);
+        ctx.fill();
+    }
+
+    for (let i = 0; i < state.explosions.length; ++i) {
+        const explosion = state.explosions[i];
+
+        ctx.strokeStyle = explosion.is_reaction ? "#f0f" : explosion.is_white ? "#aaa" : "#000";
+        ctx.beginPath();
+        ctx.arc(explosion.x * CELL_SIZE, explosion.y * CELL_SIZE, CELL_SIZE * explosion.radius, 0, 2 * Math.PI);
+        ctx.stroke();
+    }
+
+    ctx.strokeStyle = "#f00";
+    for (let i = 0; i < state.locks.length; ++i) {
+        const lock = state.locks[i];
+        const size = CELL_SIZE * lock.radius + 4;
+
+        ctx.setLineDash(lock.progress < 1 ? [2, 4] : []);
+        ctx.strokeRect(lock.x * CELL_SIZE - size, lock.y * CELL_SIZE - size, 2 * size, 2 * size);
+    }
+    ctx.setLineDash([]);
+
+    ctx.strokeStyle = "#000";
 
     const player = state.player;
     if (player != null) {
+        ctx.fillStyle = "#000";
+        ctx.fillText(`hp ${player.hp}/${player.max_hp}`, 50, 80);
+        ctx.strokeRect(120, 74, 60, 6);
+        ctx.fillStyle = player.is_ultimate_ready ? "#f0f" : "#08f";
+        ctx.fillRect(120, 74, (60 * player.energy) / player.max_energy, 6);
+        ctx.fillStyle = "#000";
+        if (player.weapon != null) {
+            ctx.fillText(player.weapon, 50, 95);
+        }
+        if (player.gun != null) {
+            const gun = player.gun;
+            if (gun.reload_progress != null) {
+                ctx.fillText("reloading", 50, 110);
+                ctx.fillRect(110, 104, 60 * gun.reload_progress, 6);
+            } else if (gun.ammo != null) {
+                ctx.fillText(`ammo ${gun.ammo}/${gun.magazine}`, 50, 110);
+            }
+            if (gun.charge > 0) {
+                ctx.strokeStyle = "#08f";
+                ctx.beginPath();
+                ctx.arc(player.x * CELL_SIZE, player.y * CELL_SIZE, CELL_SIZE * player.radius + 2 + 6 * gun.charge, 0, 2 * Math.PI);
+                ctx.stroke();
+                ctx.strokeStyle = "#000";
+            }
+            if (gun.heat > 0) {
+                ctx.fillStyle = gun.is_overheated ? "#f00" : "#f80";
+                ctx.fillRect(50, 115, 60 * gun.heat, 4);
+            }
+        }
+
+        for (let i = 0; i < player.effects.length; ++i) {
+            const effect = player.effects[i];
+            ctx.fillStyle = PICKUP_COLORS[effect.kind];
+            ctx.fillText(`${effect.kind} ${Math.ceil(effect.ticks / 50)}s`, 50, 140 + 15 * i);
+        }
+
+        for (let i = 0; i < player.statuses.length; ++i) {
+            const status = player.statuses[i];
+            const stacks = status.stacks > 1 ? ` x${status.stacks}` : "";
+            ctx.fillStyle = "#08f";
+            ctx.fillText(`${status.kind}${stacks} ${Math.ceil(status.ticks / 50)}s`, 150, 140 + 15 * i);
+        }
+
+        if (player.dash != null && player.dash.cooldown > 0) {
+            ctx.fillStyle = "#0a0";
+            ctx.fillRect(50, 125, 60 * (1 - player.dash.cooldown), 4);
+        }
+
+        ctx.globalAlpha = player.dash != null && player.dash.is_invulnerable ? 0.4 : 1;
+        ctx.fillStyle = player.is_white ? "#fff" : "#000";
         ctx.beginPath();
         ctx.arc(player.x * CELL_SIZE, player.y * CELL_SIZE, CELL_SIZE * player.radius, 0, 2 * Math.PI);
         ctx.fill();
         ctx.stroke();
+        ctx.globalAlpha = 1;
+    }
+}
+
+// стрелки поля пути, по которому враги обходят препятствия
+let isFlowFieldShown = false;
+document.addEventListener("keydown", event => {
+    if (event.code == "KeyG") {
+        isFlowFieldShown = !isFlowFieldShown;
     }
+});
+
+function drawFlowField(field) {
+    const size = field.cell_size * CELL_SIZE;
+
+    ctx.strokeStyle = field.is_building ? "#fa0" : "#0af";
+    ctx.beginPath();
+    for (let i = 0; i < field.directions.length; ++i) {
+        const direction = field.directions[i];
+        if (direction == null) continue;
+
+        const x = (field.x + ((i % field.width) + 0.5) * field.cell_size) * CELL_SIZE;
+        const y = (field.y + (Math.floor(i / field.width) + 0.5) * field.cell_size) * CELL_SIZE;
+        const [dx, dy] = direction;
+        ctx.moveTo(x - (dx * size) / 3, y - (dy * size) / 3);
+        ctx.lineTo(x + (dx * size) / 3, y + (dy * size) / 3);
+        ctx.rect(x + (dx * size) / 3 - 1, y + (dy * size) / 3 - 1, 2, 2);
+    }
+    ctx.stroke();
+    ctx.strokeStyle = "#000";
 }
 
 let isPause = false;
@@ -190,5 +427,8 @@ setInterval(() => {
     }
 
     draw(state);
+    if (isFlowFieldShown) {
+        drawFlowField(JSON.parse(game.get_flow_field()));
+    }
     game.step();
 }, 20);